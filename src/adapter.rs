@@ -195,7 +195,9 @@ impl GCAdapterWaiter {
         }
     }
 
-    pub fn get_pads(&self) -> [Option<GCPad>; 4] {
+    /// The controller in each port, or `None` if the adapter has just been disconnected.
+    /// The next call waits for the adapter to come back.
+    pub fn get_pads(&self) -> Option<[Option<GCPad>; 4]> {
         let adapter_lock = self.adapter.0.lock();
         let out = if let Some(adapter) = adapter_lock.as_ref() {
             let out = adapter.get_pads(&self.logger);
@@ -207,20 +209,18 @@ impl GCAdapterWaiter {
             if *newly_none {
                 log!(self.logger, "GC adapter disconnected.");
                 *newly_none = false;
-                return Default::default();
+                return None;
             } else {
                 drop(newly_none);
                 self.wait_for_controller();
-                self.adapter.0.lock().as_ref().map(|ad| ad.get_pads(&self.logger)).unwrap_or_default()
+                self.adapter.0.lock().as_ref().and_then(|ad| ad.get_pads(&self.logger))
             }
         };
-        if let Some(out) = out {
-            out
-        } else {
+        if out.is_none() {
             *self.newly_none.lock() = false;
             *self.adapter.0.lock() = None;
-            Default::default()
         }
+        out
     }
 
     pub fn send_rumble(&self, rumble: [u8; 4]) {
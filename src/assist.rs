@@ -0,0 +1,78 @@
+use crate::config::{ALL_GBUTTONS, GButton, PortConfig};
+use std::time::{Duration, Instant};
+
+/// Running state of the button accessibility assists for one port.
+pub struct ButtonAssistState {
+    previous: GButton,
+    /// Sticky buttons that are currently toggled on.
+    latched: GButton,
+    /// When each button in `ALL_GBUTTONS` may be released, if it was pressed recently.
+    hold_until: [Option<Instant>; ALL_GBUTTONS.len()],
+}
+
+impl Default for ButtonAssistState {
+    fn default() -> Self {
+        Self { previous: GButton::empty(), latched: GButton::empty(), hold_until: Default::default() }
+    }
+}
+
+impl ButtonAssistState {
+    /// Apply the sticky buttons and minimum hold time to the buttons pressed on the controller.
+    pub fn apply(&mut self, port: &PortConfig, buttons: GButton, now: Instant) -> GButton {
+        let pressed = buttons - self.previous;
+        self.previous = buttons;
+
+        self.latched ^= pressed & port.sticky_buttons;
+        self.latched &= port.sticky_buttons;
+        let mut out = (buttons - port.sticky_buttons) | self.latched;
+
+        let hold = Duration::from_millis(port.min_hold_time.into());
+        for ((_, button), until) in ALL_GBUTTONS.iter().zip(&mut self.hold_until) {
+            if pressed.contains(*button) && port.hold_buttons.contains(*button) {
+                *until = Some(now + hold);
+            }
+            match until {
+                Some(time) if now < *time => out.insert(*button),
+                _ => *until = None,
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(8);
+
+    #[test]
+    fn sticky_buttons_toggle_on_each_press() {
+        let port = PortConfig { sticky_buttons: GButton::A, ..Default::default() };
+        let mut state = ButtonAssistState::default();
+        let now = Instant::now();
+        let inputs = [GButton::A, GButton::A, GButton::empty(), GButton::empty(), GButton::A, GButton::empty()];
+        let outputs: Vec<_> = (0..).zip(inputs).map(|(i, b)| state.apply(&port, b, now + FRAME * i)).collect();
+        let a = GButton::A;
+        let none = GButton::empty();
+        assert!(outputs == [a, a, a, a, none, none]);
+        // other buttons pass straight through
+        assert!(state.apply(&port, GButton::B, now + FRAME * 6) == GButton::B);
+    }
+
+    #[test]
+    fn hold_buttons_stretch_taps() {
+        let port = PortConfig { hold_buttons: GButton::B, min_hold_time: 100, ..Default::default() };
+        let mut state = ButtonAssistState::default();
+        let now = Instant::now();
+        assert!(state.apply(&port, GButton::B, now) == GButton::B);
+        assert!(state.apply(&port, GButton::empty(), now + FRAME) == GButton::B);
+        assert!(state.apply(&port, GButton::empty(), now + Duration::from_millis(99)) == GButton::B);
+        assert!(state.apply(&port, GButton::empty(), now + Duration::from_millis(100)).is_empty());
+        // a press longer than the minimum ends when it's released
+        let later = now + Duration::from_secs(1);
+        assert!(state.apply(&port, GButton::B, later) == GButton::B);
+        assert!(state.apply(&port, GButton::B, later + Duration::from_millis(300)) == GButton::B);
+        assert!(state.apply(&port, GButton::empty(), later + Duration::from_millis(308)).is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    config::{Calibration, StickCalibration},
+    stick::{GateRecorder, TriggerRecorder},
+};
+use std::{
+    f64::consts::{PI, TAU},
+    time::{Duration, Instant},
+};
+
+// How long the sticks must be left alone to measure their centers
+const REST_TIME: Duration = Duration::from_secs(2);
+// Resting sticks may wobble this far, in fractions of the stick range
+const REST_TOLERANCE: f64 = 0.03;
+// How many times each stick must be rotated around its gate
+const ROTATIONS: f64 = 3.0;
+// Only positions this far out count towards a rotation
+const ROTATION_MIN_RADIUS: f64 = 0.5;
+// A trigger counts as released when it's this close to its resting value
+const TRIGGER_RELEASE_TOLERANCE: u8 = 16;
+// Each step is abandoned if it takes longer than this
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the calibration routine wants the daemon to do after a frame.
+pub enum Progress {
+    /// Tell the user what to do next.
+    Prompt(&'static str),
+    Continue,
+    Done(Calibration),
+    Failed(&'static str),
+}
+
+enum Step {
+    Rest { since: Instant, start: [(i16, i16); 2], sum: [(f64, f64); 2], count: u32 },
+    Rotate { since: Instant, gates: Box<[GateRecorder; 2]>, last_angle: [Option<f64>; 2], turned: [f64; 2] },
+    Triggers { since: Instant, triggers: [TriggerRecorder; 2] },
+}
+
+/// Guides the user through measuring a controller's stick centers, gates and trigger ranges.
+#[derive(Default)]
+pub struct Calibrator {
+    step: Option<Step>,
+    centers: [(i16, i16); 2],
+    gates: [Vec<f64>; 2],
+}
+
+impl Calibrator {
+    /// Feed the calibration routine the uncentered stick positions and raw trigger values for a frame.
+    pub fn update(&mut self, sticks: [(i16, i16); 2], triggers: [u8; 2], now: Instant) -> Progress {
+        let range = f64::from(i16::MAX);
+        let step = match &mut self.step {
+            None => {
+                self.step = Some(Step::Rest { since: now, start: sticks, sum: Default::default(), count: 0 });
+                return Progress::Prompt("Let go of both sticks and the triggers.");
+            },
+            Some(step) => step,
+        };
+        match step {
+            Step::Rest { since, start, sum, count } => {
+                let moved = sticks.iter().zip(start.iter()).any(|(pos, start)| {
+                    (f64::from(pos.0) - f64::from(start.0)).hypot(f64::from(pos.1) - f64::from(start.1))
+                        > range * REST_TOLERANCE
+                });
+                if moved {
+                    *since = now;
+                    *start = sticks;
+                    *sum = Default::default();
+                    *count = 0;
+                    return Progress::Continue;
+                }
+                for (sum, pos) in sum.iter_mut().zip(sticks) {
+                    sum.0 += f64::from(pos.0);
+                    sum.1 += f64::from(pos.1);
+                }
+                *count += 1;
+                if now - *since < REST_TIME {
+                    return Progress::Continue;
+                }
+                let count = f64::from(*count);
+                self.centers = sum.map(|(x, y)| ((x / count).round() as i16, (y / count).round() as i16));
+                self.step = Some(Step::Rotate {
+                    since: now,
+                    gates: Default::default(),
+                    last_angle: Default::default(),
+                    turned: Default::default(),
+                });
+                Progress::Prompt("Slowly rotate both sticks around the edges of their gates 3 times.")
+            },
+            Step::Rotate { since, gates, last_angle, turned } => {
+                for (pos, center, gate, last, turned) in
+                    itertools::izip!(sticks, self.centers, gates.iter_mut(), last_angle.iter_mut(), turned.iter_mut())
+                {
+                    let (x, y) = (pos.0.saturating_sub(center.0), pos.1.saturating_sub(center.1));
+                    gate.record((x, y));
+                    let (fx, fy) = (f64::from(x), f64::from(y));
+                    if fx.hypot(fy) >= range * ROTATION_MIN_RADIUS {
+                        let angle = fy.atan2(fx);
+                        if let Some(last) = last {
+                            *turned += ((angle - *last + PI).rem_euclid(TAU) - PI).abs();
+                        }
+                        *last = Some(angle);
+                    } else {
+                        *last = None;
+                    }
+                }
+                if turned.iter().all(|&t| t >= ROTATIONS * TAU)
+                    && let [Some(main), Some(c)] = [gates[0].finish(), gates[1].finish()]
+                {
+                    self.gates = [main, c];
+                    self.step = Some(Step::Triggers { since: now, triggers: Default::default() });
+                    return Progress::Prompt("Let go of the sticks, then press both triggers all the way in.");
+                }
+                if now - *since > STEP_TIMEOUT {
+                    Progress::Failed("The sticks weren't rotated all the way around in time.")
+                } else {
+                    Progress::Continue
+                }
+            },
+            Step::Triggers { since, triggers: recorders } => {
+                for (recorder, value) in recorders.iter_mut().zip(triggers) {
+                    recorder.record(value);
+                }
+                let ranges = [recorders[0].finish(), recorders[1].finish()];
+                let released = ranges.iter().zip(triggers).all(|(range, value)| {
+                    range.is_some_and(|(min, _)| value <= min.saturating_add(TRIGGER_RELEASE_TOLERANCE))
+                });
+                if let ([Some(left), Some(right)], true) = (ranges, released) {
+                    let [main_gate, c_gate] = std::mem::take(&mut self.gates);
+                    Progress::Done(Calibration {
+                        main_stick: StickCalibration { center: self.centers[0], gate: main_gate },
+                        c_stick: StickCalibration { center: self.centers[1], gate: c_gate },
+                        triggers: Some([left, right]),
+                    })
+                } else if now - *since > STEP_TIMEOUT {
+                    Progress::Failed("The triggers weren't pressed all the way in time.")
+                } else {
+                    Progress::Continue
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(8);
+    const MAIN_CENTER: (i16, i16) = (300, -200);
+    const C_CENTER: (i16, i16) = (0, 100);
+    const GATE_RADIUS: f64 = 0.8;
+
+    /// Feed the calibrator a frame of `input` every `FRAME` until it finishes or `limit` is reached. Returns the
+    /// prompts with when they were shown, and how it finished.
+    fn run(
+        input: impl Fn(Duration) -> ([(i16, i16); 2], [u8; 2]),
+        limit: Duration,
+    ) -> (Vec<(Duration, &'static str)>, Option<Progress>) {
+        let mut calibrator = Calibrator::default();
+        let start = Instant::now();
+        let mut prompts = Vec::new();
+        let mut time = Duration::ZERO;
+        while time <= limit {
+            let (sticks, triggers) = input(time);
+            match calibrator.update(sticks, triggers, start + time) {
+                Progress::Prompt(text) => prompts.push((time, text)),
+                Progress::Continue => (),
+                finished => return (prompts, Some(finished)),
+            }
+            time += FRAME;
+        }
+        (prompts, None)
+    }
+
+    /// Both sticks going around their gates once a second, starting from the right at `start`.
+    fn rotating(time: Duration, start: Duration) -> [(i16, i16); 2] {
+        let angle = (time - start).as_secs_f64() * TAU;
+        let radius = GATE_RADIUS * f64::from(i16::MAX);
+        let offset = ((radius * angle.cos()) as i16, (radius * angle.sin()) as i16);
+        [MAIN_CENTER, C_CENTER].map(|center| (center.0 + offset.0, center.1 + offset.1))
+    }
+
+    /// Rests until 2.5s, rotates the sticks 4 times, then presses the triggers from 7s to 7.5s if `press`.
+    fn user(time: Duration, press: bool) -> ([(i16, i16); 2], [u8; 2]) {
+        let secs = time.as_secs_f64();
+        let sticks = if (2.5..6.5).contains(&secs) {
+            rotating(time, Duration::from_millis(2500))
+        } else {
+            // a little wobble while resting
+            let wobble = if time.as_millis().is_multiple_of(16) { 50 } else { -50 };
+            [(MAIN_CENTER.0 + wobble, MAIN_CENTER.1), (C_CENTER.0, C_CENTER.1 - wobble)]
+        };
+        let trigger = if press && (7.0..7.5).contains(&secs) { 230 } else { 30 };
+        (sticks, [trigger, trigger + 5])
+    }
+
+    #[test]
+    fn calibrates() {
+        let (prompts, finished) = run(|time| user(time, true), Duration::from_secs(10));
+        assert_eq!(prompts.len(), 3);
+        assert_eq!(prompts[1].0, Duration::from_secs(2));
+        let Some(Progress::Done(calibration)) = finished else {
+            panic!("calibration didn't finish");
+        };
+        assert_eq!(calibration.main_stick.center, MAIN_CENTER);
+        assert_eq!(calibration.c_stick.center, C_CENTER);
+        for stick in calibration.sticks() {
+            assert_eq!(stick.gate.len(), crate::config::CALIBRATION_SAMPLES);
+            assert!(stick.gate.iter().all(|r| (r - GATE_RADIUS).abs() < 0.01));
+        }
+        assert_eq!(calibration.triggers, Some([(30, 230), (35, 235)]));
+    }
+
+    #[test]
+    fn moving_restarts_the_rest() {
+        let (prompts, _) = run(
+            |time| {
+                let bump = if time == Duration::from_millis(1504) { 5000 } else { 0 };
+                ([(bump, 0), (0, 0)], [0, 0])
+            },
+            Duration::from_secs(5),
+        );
+        assert_eq!(
+            prompts.iter().map(|(time, _)| *time).collect::<Vec<_>>(),
+            [Duration::ZERO, Duration::from_millis(3512)]
+        );
+    }
+
+    #[test]
+    fn fails_without_rotating() {
+        let (prompts, finished) = run(|_| ([(0, 0); 2], [0, 0]), Duration::from_secs(40));
+        assert_eq!(prompts.len(), 2);
+        assert!(matches!(finished, Some(Progress::Failed(_))));
+    }
+
+    #[test]
+    fn fails_without_pressing_triggers() {
+        let (prompts, finished) = run(|time| user(time, false), Duration::from_secs(40));
+        assert_eq!(prompts.len(), 3);
+        assert!(matches!(finished, Some(Progress::Failed(_))));
+    }
+}
@@ -1,15 +1,81 @@
-const CONFIG_PATH: &str = "gc-x.ini";
+use crate::stick;
+use itertools::Itertools;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const CONFIG_FILE: &str = "gc-x.toml";
+/// Settings from before the switch to TOML, migrated when there's no `CONFIG_FILE` yet.
+const LEGACY_CONFIG_FILE: &str = "gc-x.ini";
+/// Folder for `CONFIG_FILE` inside the user's config directory.
+const CONFIG_DIR: &str = "GC-X";
+/// The settings file must stay unchanged this long before it's reloaded, so it isn't read halfway through a write.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Bump this and convert older files in `Config::from_toml` whenever existing settings change meaning.
+const CONFIG_VERSION: u32 = 1;
+
+// Section and key names in `LEGACY_CONFIG_FILE`
 mod section {
     pub const BUTTONS: &str = "Buttons";
     pub const CONTROLLER: &str = "Controller";
     pub const APPLICATION: &str = "Application";
+
+    pub fn port(i: usize) -> String {
+        format!("Port{}", i + 1)
+    }
+
+    pub fn slot(i: usize) -> String {
+        format!("Slot{}", i + 1)
+    }
 }
 mod item {
     pub const AUTO_RECENTER: &str = "AutoRecenter";
+    pub const RECENTER_COMBO: &str = "RecenterCombo";
+    pub const RECENTER_COMBO_TIME: &str = "RecenterComboTime";
+    pub const SUPPRESS_RECENTER_COMBO: &str = "SuppressRecenterCombo";
+    pub const PRESS_TO_JOIN: &str = "PressToJoin";
+    pub const DISCONNECT_GRACE_TIME: &str = "DisconnectGraceTime";
+    pub const ALWAYS_CONNECTED: &str = "AlwaysConnected";
+    pub const RUMBLE_PERIOD: &str = "RumblePeriod";
+    pub const RUMBLE_MAX_TIME: &str = "RumbleMaxTime";
+    pub const IDENTIFY_ON_CONNECT: &str = "IdentifyOnConnect";
     pub const DEADZONE: &str = "Deadzone";
+    pub const COMBINED_TRIGGERS: &str = "CombinedTriggers";
+    pub const COMBINED_TRIGGERS_CENTER: &str = "CombinedTriggersCenter";
     pub const CLOSE_TO_TRAY: &str = "CloseToTray";
+    // per-stick settings, prefixed with the stick name
+    pub const HYSTERESIS: &str = "Hysteresis";
+    pub const SMOOTHING_TIME: &str = "SmoothingTime";
+    pub const SNAPBACK_TIME: &str = "SnapbackTime";
+    pub const SNAP_WINDOW: &str = "SnapWindow";
+    pub const SNAP_ANGLES: &str = "SnapAngles";
+    pub const NOTCHES: &str = "Notches";
+    pub const TREMOR_TIME: &str = "TremorTime";
+    pub const ENABLED: &str = "Enabled";
+    pub const RAW_MODE: &str = "RawMode";
+    pub const RUMBLE_LARGE_WEIGHT: &str = "RumbleLargeWeight";
+    pub const RUMBLE_STRENGTH: &str = "RumbleStrength";
+    pub const STICKY_BUTTONS: &str = "StickyButtons";
+    pub const HOLD_BUTTONS: &str = "HoldButtons";
+    pub const MIN_HOLD_TIME: &str = "MinHoldTime";
+    pub const DRIFT_COMPENSATION: &str = "DriftCompensation";
+    pub const DRIFT_WINDOW: &str = "DriftWindow";
+    pub const DRIFT_IDLE_TIME: &str = "DriftIdleTime";
+    pub const DRIFT_MAX_OFFSET: &str = "DriftMaxOffset";
+    pub const CALIBRATED: &str = "Calibrated";
+    pub const TRIGGER_RANGE: &str = "TriggerRange";
+    pub const PORTS: &str = "Ports";
+    pub const STICK_MERGE: &str = "StickMerge";
+    // per-stick calibration, prefixed with the stick name
+    pub const CENTER: &str = "Center";
+    pub const GATE: &str = "Gate";
 }
 
+/// Prefixes for per-stick settings in a port section.
+const STICK_PREFIXES: [&str; 2] = ["Main", "C"];
+
 macro_rules! log {
     ($logger:expr, $fmt:literal) => {{
         $logger(concat!($fmt, "\r\n"))
@@ -20,7 +86,7 @@ macro_rules! log {
 }
 
 bitflags::bitflags! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
     pub struct XButton: u16 {
         const DPAD_UP = 0x0001;
         const DPAD_DOWN = 0x0002;
@@ -41,7 +107,7 @@ bitflags::bitflags! {
 }
 
 bitflags::bitflags! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Default, PartialEq, Eq)]
     pub struct GButton: u16 {
         const A = 0x0001;
         const B = 0x0002;
@@ -76,8 +142,46 @@ pub const XBUTTONS: [(&str, XButton); 15] = [
     ("Down", XButton::DPAD_DOWN),
 ];
 
-pub fn xbutton_names() -> Vec<&'static str> {
-    XBUTTONS.iter().copied().map(|(name, _)| name).collect()
+/// Name used for a GC button that isn't mapped to anything.
+pub const UNMAPPED: &str = "None";
+
+/// Names for the mapping selector: "None", followed by every Xbox button.
+pub fn mapping_names() -> Vec<String> {
+    Some(UNMAPPED).into_iter().chain(XBUTTONS.iter().copied().map(|(name, _)| name)).map(String::from).collect()
+}
+
+/// Index of a mapping in `mapping_names`, if it's a single button or unmapped.
+pub fn mapping_index(mapping: XButton) -> Option<usize> {
+    if mapping.is_empty() { Some(0) } else { XBUTTONS.iter().position(|(_, xb)| *xb == mapping).map(|i| i + 1) }
+}
+
+/// Name of a mapping, such as `A`, `LB+RB` or `None`.
+pub fn mapping_name(mapping: XButton) -> String {
+    buttons_to_string(mapping, &XBUTTONS)
+}
+
+/// Mapping corresponding to a name from `mapping_name`.
+pub fn mapping_from_name(name: &str) -> Option<XButton> {
+    parse_buttons(name, &XBUTTONS)
+}
+
+/// Parse a set of buttons such as `A`, `LB+RB` or `None`.
+fn parse_buttons<B: bitflags::Flags + Copy>(text: &str, names: &[(&str, B)]) -> Option<B> {
+    if text == UNMAPPED {
+        return Some(B::empty());
+    }
+    text.split('+').try_fold(B::empty(), |acc, name| {
+        let name = name.trim();
+        names.iter().copied().find(|(b_name, _)| *b_name == name).map(|(_, b)| acc.union(b))
+    })
+}
+
+fn buttons_to_string<B: bitflags::Flags + Copy>(buttons: B, names: &[(&str, B)]) -> String {
+    if buttons.is_empty() {
+        UNMAPPED.into()
+    } else {
+        names.iter().copied().filter(|(_, b)| buttons.contains(*b)).map(|(name, _)| name).join("+")
+    }
 }
 
 pub const GBUTTONS: [(&str, GButton); 10] = [
@@ -93,97 +197,1075 @@ pub const GBUTTONS: [(&str, GButton); 10] = [
     ("Down", GButton::DPAD_DOWN),
 ];
 
-#[derive(Clone, PartialEq, Eq)]
+/// Every GC button, including the digital presses of the triggers.
+pub const ALL_GBUTTONS: [(&str, GButton); 12] = [
+    ("A", GButton::A),
+    ("B", GButton::B),
+    ("X", GButton::X),
+    ("Y", GButton::Y),
+    ("Z", GButton::Z),
+    ("L", GButton::L),
+    ("R", GButton::R),
+    ("Start", GButton::START),
+    ("Left", GButton::DPAD_LEFT),
+    ("Right", GButton::DPAD_RIGHT),
+    ("Up", GButton::DPAD_UP),
+    ("Down", GButton::DPAD_DOWN),
+];
+
+// Button sets are written as names joined by `+`, the same as in the old INI file
+macro_rules! serde_buttons {
+    ($type:ty, $names:expr) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&buttons_to_string(*self, &$names))
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                parse_buttons(&text, &$names).ok_or_else(|| D::Error::custom(format!("invalid buttons `{}`", text)))
+            }
+        }
+    };
+}
+
+serde_buttons!(XButton, XBUTTONS);
+serde_buttons!(GButton, ALL_GBUTTONS);
+
+/// A button mapping is written as a table from GC button names to Xbox buttons.
+/// GC buttons missing from the table keep their default mapping.
+mod mapping {
+    use super::{Config, Deserialize, Deserializer, GBUTTONS, Serializer, XButton};
+    use serde::de::Error as _;
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(buttons: &[XButton; GBUTTONS.len()], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(GBUTTONS.iter().map(|(name, _)| name).zip(buttons))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[XButton; GBUTTONS.len()], D::Error> {
+        let mut buttons = Config::default().buttons;
+        for (name, xb) in BTreeMap::<String, XButton>::deserialize(deserializer)? {
+            let gc = GBUTTONS
+                .iter()
+                .position(|(gc_name, _)| *gc_name == name)
+                .ok_or_else(|| D::Error::custom(format!("unknown GC button `{}`", name)))?;
+            buttons[gc] = xb;
+        }
+        Ok(buttons)
+    }
+}
+
+/// Like `mapping`, for a mapping profile that may not exist. GC buttons missing from a profile follow the global
+/// mapping instead, which `Config::fill_slot_mappings` takes care of once the whole file has been read.
+mod optional_mapping {
+    use super::{Deserializer, GBUTTONS, Serializer, XButton, mapping};
+
+    pub fn serialize<S: Serializer>(
+        buttons: &Option<[XButton; GBUTTONS.len()]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match buttons {
+            Some(buttons) => mapping::serialize(buttons, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[XButton; GBUTTONS.len()]>, D::Error> {
+        mapping::deserialize(deserializer).map(Some)
+    }
+}
+
+// The file may list fewer than 4 ports or slots, the rest keep their defaults
+fn deserialize_ports<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[PortConfig; 4], D::Error> {
+    fill_defaults(Vec::deserialize(deserializer)?, Config::default().ports)
+}
+
+fn deserialize_slots<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[SlotConfig; 4], D::Error> {
+    fill_defaults(Vec::deserialize(deserializer)?, Config::default().slots)
+}
+
+fn fill_defaults<T, E: serde::de::Error>(list: Vec<T>, mut defaults: [T; 4]) -> Result<[T; 4], E> {
+    if list.len() > defaults.len() {
+        return Err(E::custom(format!("expected at most {} entries, found {}", defaults.len(), list.len())));
+    }
+    for (default, item) in defaults.iter_mut().zip(list) {
+        *default = item;
+    }
+    Ok(defaults)
+}
+
+/// Ports are numbered from 1 in the file, like on the adapter, and from 0 everywhere else.
+mod port_numbers {
+    use super::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error as _;
+
+    pub fn serialize<S: Serializer>(ports: &[usize], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ports.iter().map(|port| port + 1))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
+        Vec::<usize>::deserialize(deserializer)?
+            .into_iter()
+            .map(|port| match port {
+                1..=4 => Ok(port - 1),
+                _ => Err(D::Error::custom(format!("invalid port {}, expected 1 to 4", port))),
+            })
+            .collect()
+    }
+}
+
+/// A single stick axis on the Xbox target.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StickAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+pub const STICK_AXES: [(&str, StickAxis); 4] = [
+    ("LeftX", StickAxis::LeftX),
+    ("LeftY", StickAxis::LeftY),
+    ("RightX", StickAxis::RightX),
+    ("RightY", StickAxis::RightY),
+];
+
+/// Whether a port's inputs skip the deadzone, gate scaling and recentering, for controllers that send exact values.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RawMode {
+    Off,
+    On,
+    /// Switch to raw mode once the main stick has been moved around for a while and has only been seen at a few
+    /// exact positions, like a digital controller's.
+    Auto,
+}
+
+pub const RAW_MODES: [(&str, RawMode); 3] = [("Off", RawMode::Off), ("On", RawMode::On), ("Auto", RawMode::Auto)];
+
+/// Processing settings for one stick. The filters and snapping are off when set to 0.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickConfig {
+    /// Movement smaller than this is ignored, in % of the stick range.
+    pub hysteresis: u8,
+    /// Time constant of the low-pass filter, in milliseconds.
+    pub smoothing_time: u16,
+    /// Crossings of the center faster than this many milliseconds after a hard tilt are treated as snapback.
+    pub snapback_time: u16,
+    /// Inputs within this many degrees of a notch snap to its target angle.
+    pub snap_window: u8,
+    /// Target angles to snap to, in degrees counterclockwise from the right.
+    pub snap_angles: Vec<u16>,
+    /// Where each of the snap angles' notches actually sits on this controller, in degrees.
+    /// If this doesn't match `snap_angles` in length, the target angles are used as-is.
+    pub notches: Vec<f64>,
+    /// The stick must point the same way for this many milliseconds before it moves, to filter out tremors.
+    pub tremor_time: u16,
+}
+
+impl Default for StickConfig {
+    fn default() -> Self {
+        Self {
+            hysteresis: 0,
+            smoothing_time: 0,
+            snapback_time: 0,
+            snap_window: 0,
+            snap_angles: (0..360).step_by(45).collect(),
+            notches: Vec::new(),
+            tremor_time: 0,
+        }
+    }
+}
+
+fn parse_list<T: std::str::FromStr>(text: &str) -> Option<Vec<T>> {
+    text.split_whitespace().map(|s| s.parse().ok()).collect()
+}
+
+/// Number of evenly spaced angles at which a calibrated gate's radius is measured, same as Dolphin.
+pub const CALIBRATION_SAMPLES: usize = 32;
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickCalibration {
+    pub center: (i16, i16),
+    /// Distance from the center to the edge of the gate, as a fraction of the full stick range,
+    /// at `CALIBRATION_SAMPLES` angles going counterclockwise from the right.
+    /// If empty, the gate is assumed to be the same as an official controller's.
+    pub gate: Vec<f64>,
+}
+
+/// Measurements of a specific controller, replacing the defaults for official controllers.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Calibration {
+    pub main_stick: StickCalibration,
+    pub c_stick: StickCalibration,
+    /// Resting and fully pressed values of the left and right triggers.
+    pub triggers: Option<[(u8, u8); 2]>,
+}
+
+impl Calibration {
+    pub fn sticks_mut(&mut self) -> [&mut StickCalibration; 2] {
+        [&mut self.main_stick, &mut self.c_stick]
+    }
+
+    pub fn sticks(&self) -> [&StickCalibration; 2] {
+        [&self.main_stick, &self.c_stick]
+    }
+}
+
+/// Settings that apply to a single controller port.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortConfig {
+    /// Controllers in a disabled port are ignored completely.
+    pub enabled: bool,
+    pub main_stick: StickConfig,
+    pub c_stick: StickConfig,
+    pub raw_mode: RawMode,
+    /// How much the large motor counts towards the rumble intensity, in %. The small motor makes up the rest.
+    /// At 50, either motor at full speed gives full rumble.
+    pub rumble_large_weight: u8,
+    /// Scales the rumble intensity, in %.
+    pub rumble_strength: u16,
+    /// Buttons that toggle between pressed and released each time they're pressed.
+    pub sticky_buttons: GButton,
+    /// Buttons that stay pressed for at least `min_hold_time`, however briefly they're tapped.
+    pub hold_buttons: GButton,
+    /// In milliseconds.
+    pub min_hold_time: u16,
+    /// Slowly move the stick centers towards where the sticks rest.
+    pub drift_compensation: bool,
+    /// A stick only counts as resting while it's this close to its center, in % of the stick range.
+    pub drift_window: u8,
+    /// How long a stick must rest before its center starts moving, in milliseconds.
+    pub drift_idle_time: u16,
+    /// How far the center may move away from where it was last set, in % of the stick range.
+    pub drift_max_offset: u8,
+    /// Applied whenever a controller connects to this port.
+    pub calibration: Option<Calibration>,
+}
+
+impl Default for PortConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            main_stick: Default::default(),
+            c_stick: Default::default(),
+            raw_mode: RawMode::Off,
+            rumble_large_weight: 50,
+            rumble_strength: 100,
+            sticky_buttons: GButton::empty(),
+            hold_buttons: GButton::empty(),
+            min_hold_time: 200,
+            drift_compensation: false,
+            drift_window: 8,
+            drift_idle_time: 2000,
+            drift_max_offset: 10,
+            calibration: None,
+        }
+    }
+}
+
+impl PortConfig {
+    pub fn sticks_mut(&mut self) -> [&mut StickConfig; 2] {
+        [&mut self.main_stick, &mut self.c_stick]
+    }
+}
+
+/// How the sticks and triggers of several controllers feeding one virtual controller are combined.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StickMerge {
+    /// Whichever is pushed furthest.
+    Largest,
+    /// The first port's, unless it's at rest.
+    Primary,
+}
+
+pub const STICK_MERGES: [(&str, StickMerge); 2] = [("Largest", StickMerge::Largest), ("Primary", StickMerge::Primary)];
+
+/// Settings for one virtual Xbox controller.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlotConfig {
+    /// The ports feeding this virtual controller, starting from 0. It exists while any of them has a controller.
+    #[serde(with = "port_numbers")]
+    pub ports: Vec<usize>,
+    pub stick_merge: StickMerge,
+    /// This virtual controller's own button mapping, used instead of the one in `Config::buttons`.
+    #[serde(with = "optional_mapping", skip_serializing_if = "Option::is_none")]
+    pub buttons: Option<[XButton; GBUTTONS.len()]>,
+}
+
+impl Default for SlotConfig {
+    fn default() -> Self {
+        Self { ports: Vec::new(), stick_merge: StickMerge::Largest, buttons: None }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub buttons: [usize; GBUTTONS.len()],
+    /// Layout of the file these settings were read from. Always `CONFIG_VERSION` once loaded.
+    version: u32,
+    /// The Xbox buttons pressed by each GC button. May be empty, or contain several buttons.
+    #[serde(with = "mapping")]
+    pub buttons: [XButton; GBUTTONS.len()],
     pub auto_recenter: bool,
+    /// Holding all of these buttons recenters the controller's sticks. Empty if disabled.
+    pub recenter_combo: GButton,
+    /// How long the recenter combo must be held, in milliseconds.
+    pub recenter_combo_time: u16,
+    /// Don't send the recenter combo's buttons to the game while they're all held.
+    pub suppress_recenter_combo: bool,
+    /// Give each controller the next free virtual controller when it first presses A, ignoring the slots' ports.
+    pub press_to_join: bool,
+    /// How long a controller may stop responding before it counts as disconnected, in milliseconds.
+    /// Until then its virtual controllers stay connected with nothing pressed.
+    pub disconnect_grace_time: u16,
+    /// The first this many virtual controllers exist from startup, with nothing pressed while no controller feeds them.
+    /// Since they're plugged in first and never unplugged, virtual controller N keeps player number N.
+    pub always_connected: u8,
+    /// Weak rumble is made by switching the motor on for part of each period this long, in milliseconds.
+    /// If 0, the motor is on whenever there's any rumble.
+    pub rumble_period: u16,
+    /// Rumble that goes on without a break for this many milliseconds is stopped. If 0, there's no limit.
+    pub rumble_max_time: u16,
+    /// Pulse each controller's rumble as many times as its player number when it gets a virtual controller.
+    pub identify_on_connect: bool,
     pub deadzone: u8,
+    /// If set, both triggers are merged into this axis instead of being sent as triggers.
+    pub combined_triggers: Option<StickAxis>,
+    /// Resting point of the combined trigger axis, in % of the axis range (-100 to 100).
+    pub combined_triggers_center: i8,
+    #[serde(deserialize_with = "deserialize_ports")]
+    pub ports: [PortConfig; 4],
+    #[serde(deserialize_with = "deserialize_slots")]
+    pub slots: [SlotConfig; 4],
     pub close_to_tray: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self { buttons: [0, 2, 1, 3, 5, 7, 11, 12, 13, 14], auto_recenter: false, deadzone: 5, close_to_tray: true }
+        Self {
+            version: CONFIG_VERSION,
+            buttons: [
+                XButton::A,
+                XButton::X,
+                XButton::B,
+                XButton::Y,
+                XButton::RIGHT_SHOULDER,
+                XButton::START,
+                XButton::DPAD_LEFT,
+                XButton::DPAD_RIGHT,
+                XButton::DPAD_UP,
+                XButton::DPAD_DOWN,
+            ],
+            auto_recenter: false,
+            recenter_combo: GButton::X | GButton::Y | GButton::START,
+            recenter_combo_time: 3000,
+            suppress_recenter_combo: false,
+            press_to_join: false,
+            disconnect_grace_time: 500,
+            always_connected: 0,
+            rumble_period: 80,
+            rumble_max_time: 0,
+            identify_on_connect: false,
+            deadzone: 5,
+            combined_triggers: None,
+            combined_triggers_center: 0,
+            ports: Default::default(),
+            slots: std::array::from_fn(|i| SlotConfig { ports: vec![i], ..Default::default() }),
+            close_to_tray: true,
+        }
+    }
+}
+
+/// Where the settings are kept: the path given on the command line, next to the executable if there's a config
+/// file there already (portable mode), or else in the user's config directory.
+pub fn config_path(override_path: Option<PathBuf>) -> PathBuf {
+    if let Some(path) = override_path {
+        return path;
+    }
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_owned));
+    if let Some(dir) = &exe_dir
+        && [CONFIG_FILE, LEGACY_CONFIG_FILE].iter().any(|name| dir.join(name).exists())
+    {
+        return dir.join(CONFIG_FILE);
+    }
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let user_dir = if cfg!(windows) {
+        var("APPDATA")
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    match user_dir {
+        Some(dir) => dir.join(CONFIG_DIR).join(CONFIG_FILE),
+        None => exe_dir.unwrap_or_default().join(CONFIG_FILE),
+    }
+}
+
+/// A setting that couldn't be used as written.
+#[derive(Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub file: PathBuf,
+    /// Table or INI section holding the setting, empty at the top level of the file.
+    /// Entries of `ports` and `slots` are numbered from 1, like the ports.
+    pub section: String,
+    /// Empty if the problem isn't with a single setting, like a syntax error.
+    pub key: String,
+    /// The value as written, or empty if there isn't one.
+    pub value: String,
+    pub reason: String,
+    /// What's used instead.
+    pub fallback: String,
+}
+
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if !self.section.is_empty() {
+            write!(f, " [{}]", self.section)?;
+        }
+        if !self.key.is_empty() {
+            write!(f, " {}", self.key)?;
+        }
+        if !self.value.is_empty() {
+            write!(f, " = {}", self.value)?;
+        }
+        write!(f, ": {}, using {}", self.reason, self.fallback)
+    }
+}
+
+/// Collects the problems found in one file.
+struct Problems<'a> {
+    file: &'a Path,
+    /// Section the next problems are in.
+    section: String,
+    list: Vec<ConfigProblem>,
+}
+
+impl<'a> Problems<'a> {
+    fn new(file: &'a Path) -> Self {
+        Self { file, section: String::new(), list: Vec::new() }
+    }
+
+    fn add(
+        &mut self,
+        key: &str,
+        value: impl std::fmt::Display,
+        reason: impl Into<String>,
+        fallback: impl Into<String>,
+    ) {
+        self.list.push(ConfigProblem {
+            file: self.file.to_owned(),
+            section: self.section.clone(),
+            key: key.into(),
+            value: value.to_string(),
+            reason: reason.into(),
+            fallback: fallback.into(),
+        });
+    }
+}
+
+/// Settings read from a file, along with anything in it that had to be replaced.
+pub struct Loaded {
+    pub config: Config,
+    pub problems: Vec<ConfigProblem>,
+    /// The file exists but none of it could be used, so saving over it would lose all of its settings.
+    pub unreadable: bool,
+}
+
+/// Fallback for problems that stop the whole file from being used.
+const ALL_DEFAULTS: &str = "defaults for all settings";
+/// Fallback for lines of a file that are left out.
+const LINE_DEFAULTS: &str = "defaults for the settings on that line";
+
+/// Why a gate with radii outside the usable range was rejected.
+fn gate_range_reason() -> String {
+    format!("radii out of range ({} to {})", stick::MIN_GATE_RADIUS, stick::MAX_GATE_RADIUS)
+}
+
+/// Write a list the way it's written in TOML.
+fn list_to_string<T: std::fmt::Display>(list: &[T]) -> String {
+    format!("[{}]", list.iter().join(", "))
+}
+
+/// Find the table, key and value on the line of a TOML file containing `offset`.
+fn locate(text: &str, offset: usize) -> (String, String, String) {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    // entries in arrays of tables are counted, so `[ports.main_stick]` becomes `ports[2].main_stick`
+    let mut counts = std::collections::HashMap::new();
+    let mut table = |header: &str| -> String {
+        if let Some(name) = header.strip_prefix("[[").and_then(|h| h.strip_suffix("]]")) {
+            let count = counts.entry(name.trim().to_owned()).or_insert(0);
+            *count += 1;
+            format!("{}[{}]", name.trim(), count)
+        } else {
+            let name = header.trim_start_matches('[').trim_end_matches(']').trim();
+            match name.split_once('.').and_then(|(array, rest)| Some((array, rest, counts.get(array)?))) {
+                Some((array, rest, count)) => format!("{}[{}].{}", array, count, rest),
+                None => name.to_owned(),
+            }
+        }
+    };
+    let mut section = String::new();
+    for line in text[..line_start].lines().map(str::trim).filter(|line| line.starts_with('[')) {
+        section = table(line);
+    }
+    let line = text[line_start..].lines().next().unwrap_or("").trim();
+    if line.starts_with('[') {
+        return (table(line), String::new(), String::new());
+    }
+    match line.split_once('=') {
+        Some((key, value)) => (section, key.trim().to_owned(), value.trim().to_owned()),
+        None => (section, String::new(), line.to_owned()),
     }
 }
 
 impl Config {
-    pub fn load(logger: &impl Fn(&str)) -> Self {
-        let mut config = Default::default();
-        let ini = match ini::Ini::load_from_file(CONFIG_PATH) {
-            Ok(ini) => ini,
-            Err(ini::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
-                log!(logger, "{} doesn't exist, using defaults.", CONFIG_PATH);
-                return config;
+    /// Read the settings file, migrating `LEGACY_CONFIG_FILE` if there isn't one yet.
+    /// Settings that can't be used are replaced by their defaults, and listed in the result.
+    pub fn load(path: &Path, logger: &impl Fn(&str)) -> Loaded {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // older versions kept their settings in the working directory
+                let Some(legacy_path) = [path.with_file_name(LEGACY_CONFIG_FILE), LEGACY_CONFIG_FILE.into()]
+                    .into_iter()
+                    .find(|legacy_path| legacy_path.exists())
+                else {
+                    log!(logger, "{} doesn't exist, using defaults.", path.display());
+                    return Loaded { config: Default::default(), problems: Vec::new(), unreadable: false };
+                };
+                log!(logger, "{} doesn't exist, migrating settings from {}.", path.display(), legacy_path.display());
+                let loaded = Self::load_ini(&legacy_path, logger);
+                if loaded.problems.is_empty() {
+                    // the old file is left alone, in case an older version of GC-X is still used
+                    loaded.config.save(path, logger);
+                } else {
+                    log!(
+                        logger,
+                        "Fix the problems in {} or save the settings to finish migrating.",
+                        legacy_path.display()
+                    );
+                }
+                return loaded;
             },
             Err(e) => {
-                log!(logger, "Couldn't load settings: {}\r\nUsing defaults.", e);
-                return config;
+                let mut problems = Problems::new(path);
+                problems.add("", "", format!("couldn't read the file ({})", e), ALL_DEFAULTS);
+                return Loaded { config: Default::default(), problems: problems.list, unreadable: true };
             },
         };
-        if let Some(buttons) = ini.section(Some(section::BUTTONS)) {
-            for (my_id, gbut_name) in config.buttons.iter_mut().zip(GBUTTONS.iter().copied().map(|(name, _)| name)) {
-                if let Some(xbut_name) = buttons.get(gbut_name) {
-                    if let Some(id) = XBUTTONS.iter().copied().position(|(name, _)| name == xbut_name) {
-                        *my_id = id;
-                    } else {
-                        log!(logger, "Mapping for {} button is invalid ({}), using default", gbut_name, xbut_name);
+        let mut problems = Problems::new(path);
+        let Some(mut config) = Self::from_toml(&text, &mut problems) else {
+            return Loaded { config: Default::default(), problems: problems.list, unreadable: true };
+        };
+        config.validate(&mut problems);
+        log!(logger, "Settings loaded from {}.", path.display());
+        Loaded { config, problems: problems.list, unreadable: false }
+    }
+
+    /// Read the settings file again after it was changed. Unlike `load`, a missing or empty file is a problem,
+    /// as it's most likely being written. If there are any problems, the current settings should be kept.
+    pub fn reload(path: &Path) -> Loaded {
+        let mut problems = Problems::new(path);
+        let config = match std::fs::read_to_string(path) {
+            Ok(text) if text.trim().is_empty() => {
+                problems.add("", "", "the file is empty", ALL_DEFAULTS);
+                None
+            },
+            Ok(text) => Self::from_toml(&text, &mut problems),
+            Err(e) => {
+                problems.add("", "", format!("couldn't read the file ({})", e), ALL_DEFAULTS);
+                None
+            },
+        };
+        let config = config.map(|mut config| {
+            config.validate(&mut problems);
+            config
+        });
+        // a reload with any problems isn't used at all
+        for problem in &mut problems.list {
+            problem.fallback = "the current settings".into();
+        }
+        let unreadable = config.is_none();
+        Loaded { config: config.unwrap_or_default(), problems: problems.list, unreadable }
+    }
+
+    /// Parse the contents of a config file, upgrading it from older versions.
+    /// Lines that can't be read are left out one at a time, so the rest of the file is still used.
+    fn from_toml(text: &str, problems: &mut Problems) -> Option<Self> {
+        /// Record an error and blank out the line it's on. Returns false if it isn't on a line that can be
+        /// left out, so the whole file has to be given up on.
+        fn skip_line(problems: &mut Problems, text: &mut String, e: &toml::de::Error) -> bool {
+            let reason = e.message().trim_end().replace('\n', ", ");
+            let Some(mut start) = e.span().map(|span| span.start.min(text.len())) else {
+                problems.section = String::new();
+                problems.add("", "", reason, ALL_DEFAULTS);
+                return false;
+            };
+            let line_start = |start: usize| text[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = |start: usize| text[start..].find('\n').map_or(text.len(), |i| start + i);
+            // errors found at a blank line, like an unclosed array at the end of the file, belong to the line before
+            if text[line_start(start)..line_end(start)].trim().is_empty() {
+                start = text[..line_start(start)].trim_end().len();
+            }
+            let (section, key, value) = locate(text, start);
+            problems.section = section;
+            let (line_start, line_end) = (line_start(start), line_end(start));
+            if text[line_start..line_end].trim().is_empty() {
+                problems.add(&key, value, reason, ALL_DEFAULTS);
+                return false;
+            }
+            problems.add(&key, value, reason, LINE_DEFAULTS);
+            text.replace_range(line_start..line_end, "");
+            true
+        }
+        let mut text = text.to_owned();
+        // only look at the version first, so newer files fail with a clear message instead of unknown keys
+        #[derive(Deserialize)]
+        struct Version {
+            version: Option<u32>,
+        }
+        let version = loop {
+            match toml::from_str::<Version>(&text) {
+                Ok(Version { version }) => break version,
+                Err(e) if skip_line(problems, &mut text, &e) => (),
+                Err(_) => return None,
+            }
+        };
+        problems.section = String::new();
+        match version {
+            Some(CONFIG_VERSION) | None => (),
+            Some(version) if version > CONFIG_VERSION => {
+                problems.add(
+                    "version",
+                    version,
+                    format!("written by a newer version of GC-X, this one reads up to version {}", CONFIG_VERSION),
+                    ALL_DEFAULTS,
+                );
+                return None;
+            },
+            Some(version) => {
+                problems.add("version", version, "unknown version", ALL_DEFAULTS);
+                return None;
+            },
+        }
+        loop {
+            match toml::from_str::<Self>(&text) {
+                Ok(mut config) => {
+                    config.version = CONFIG_VERSION;
+                    config.fill_slot_mappings(&text);
+                    return Some(config);
+                },
+                Err(e) if skip_line(problems, &mut text, &e) => (),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Point the GC buttons a slot's mapping profile leaves out at the global mapping, as the INI file did.
+    fn fill_slot_mappings(&mut self, text: &str) {
+        #[derive(Deserialize)]
+        struct Slot {
+            buttons: Option<toml::Table>,
+        }
+        #[derive(Deserialize)]
+        struct Slots {
+            #[serde(default)]
+            slots: Vec<Slot>,
+        }
+        let Ok(Slots { slots }) = toml::from_str(text) else {
+            return;
+        };
+        let global = self.buttons;
+        for (slot, listed) in self.slots.iter_mut().zip(slots) {
+            if let (Some(buttons), Some(listed)) = (&mut slot.buttons, listed.buttons) {
+                for (button, global, (name, _)) in itertools::izip!(buttons, global, GBUTTONS) {
+                    if !listed.contains_key(name) {
+                        *button = global;
                     }
-                } else {
-                    log!(logger, "Mapping for {} button not found, using default", gbut_name);
                 }
             }
-        } else {
-            log!(logger, "Buttons section not found, using defaults");
         }
-        fn load_bool(logger: &impl Fn(&str), section: &ini::Properties, out: &mut bool, name: &str) {
+    }
+
+    /// Reset settings that are out of range to their defaults.
+    fn validate(&mut self, problems: &mut Problems) {
+        fn check<T: PartialOrd + std::fmt::Display + Copy>(
+            problems: &mut Problems,
+            key: &str,
+            value: &mut T,
+            default: T,
+            range: std::ops::RangeInclusive<T>,
+        ) {
+            if !range.contains(value) {
+                problems.add(
+                    key,
+                    *value,
+                    format!("out of range ({} to {})", range.start(), range.end()),
+                    default.to_string(),
+                );
+                *value = default;
+            }
+        }
+        let default = Self::default();
+        problems.section = String::new();
+        check(problems, "recenter_combo_time", &mut self.recenter_combo_time, default.recenter_combo_time, 0..=60000);
+        check(
+            problems,
+            "disconnect_grace_time",
+            &mut self.disconnect_grace_time,
+            default.disconnect_grace_time,
+            0..=60000,
+        );
+        check(problems, "always_connected", &mut self.always_connected, default.always_connected, 0..=4);
+        check(problems, "rumble_period", &mut self.rumble_period, default.rumble_period, 0..=1000);
+        check(problems, "rumble_max_time", &mut self.rumble_max_time, default.rumble_max_time, 0..=60000);
+        check(problems, "deadzone", &mut self.deadzone, default.deadzone, 0..=100);
+        check(
+            problems,
+            "combined_triggers_center",
+            &mut self.combined_triggers_center,
+            default.combined_triggers_center,
+            -100..=100,
+        );
+        let default = PortConfig::default();
+        for (i, port) in self.ports.iter_mut().enumerate() {
+            for (stick, stick_name) in port.sticks_mut().into_iter().zip(["main_stick", "c_stick"]) {
+                problems.section = format!("ports[{}].{}", i + 1, stick_name);
+                let default = &default.main_stick;
+                check(problems, "hysteresis", &mut stick.hysteresis, default.hysteresis, 0..=100);
+                check(problems, "smoothing_time", &mut stick.smoothing_time, default.smoothing_time, 0..=1000);
+                check(problems, "snapback_time", &mut stick.snapback_time, default.snapback_time, 0..=1000);
+                check(problems, "snap_window", &mut stick.snap_window, default.snap_window, 0..=45);
+                check(problems, "tremor_time", &mut stick.tremor_time, default.tremor_time, 0..=1000);
+                if !stick.snap_angles.iter().all(|&a| a < 360) {
+                    problems.add(
+                        "snap_angles",
+                        list_to_string(&stick.snap_angles),
+                        "angles must be below 360",
+                        list_to_string(&default.snap_angles),
+                    );
+                    stick.snap_angles = default.snap_angles.clone();
+                }
+                if !stick.notches.iter().all(|a| (0.0..360.0).contains(a)) {
+                    problems.add("notches", list_to_string(&stick.notches), "angles must be below 360", "[]");
+                    stick.notches.clear();
+                }
+            }
+            problems.section = format!("ports[{}]", i + 1);
+            check(problems, "min_hold_time", &mut port.min_hold_time, default.min_hold_time, 0..=5000);
+            check(problems, "rumble_large_weight", &mut port.rumble_large_weight, default.rumble_large_weight, 0..=100);
+            check(problems, "rumble_strength", &mut port.rumble_strength, default.rumble_strength, 0..=1000);
+            check(problems, "drift_window", &mut port.drift_window, default.drift_window, 0..=100);
+            check(problems, "drift_idle_time", &mut port.drift_idle_time, default.drift_idle_time, 0..=60000);
+            check(problems, "drift_max_offset", &mut port.drift_max_offset, default.drift_max_offset, 0..=100);
+            if let Some(calibration) = &mut port.calibration {
+                for (stick, stick_name) in calibration.sticks_mut().into_iter().zip(["main_stick", "c_stick"]) {
+                    problems.section = format!("ports[{}].calibration.{}", i + 1, stick_name);
+                    if !matches!(stick.gate.len(), 0 | CALIBRATION_SAMPLES) {
+                        problems.add(
+                            "gate",
+                            list_to_string(&stick.gate),
+                            format!("needs {} values", CALIBRATION_SAMPLES),
+                            "[]",
+                        );
+                        stick.gate.clear();
+                    } else if !stick::valid_gate(&stick.gate) {
+                        problems.add("gate", list_to_string(&stick.gate), gate_range_reason(), "[]");
+                        stick.gate.clear();
+                    }
+                }
+                problems.section = format!("ports[{}].calibration", i + 1);
+                if let Some(triggers) = calibration.triggers
+                    && !triggers.iter().all(|(min, max)| min < max)
+                {
+                    let triggers = triggers.iter().map(|(min, max)| format!("[{}, {}]", min, max)).collect_vec();
+                    problems.add(
+                        "triggers",
+                        list_to_string(&triggers),
+                        "each trigger's resting value must be below its pressed value",
+                        "the full range",
+                    );
+                    calibration.triggers = None;
+                }
+            }
+        }
+    }
+
+    /// Read settings saved by versions of GC-X before the switch to TOML.
+    fn load_ini(path: &Path, logger: &impl Fn(&str)) -> Loaded {
+        let mut config = Self::default();
+        let mut problems = Problems::new(path);
+        let ini = match ini::Ini::load_from_file(path) {
+            Ok(ini) => ini,
+            Err(e) => {
+                problems.add("", "", format!("couldn't read the file ({})", e), ALL_DEFAULTS);
+                // the settings are saved to a new file, so the old one can't be lost
+                return Loaded { config, problems: problems.list, unreadable: false };
+            },
+        };
+        // settings added after this file format are fine to be missing, but the original ones are mentioned
+        let not_found = |section: &ini::Properties, name: &str| {
+            if !section.contains_key(name) {
+                log!(logger, "{} setting not found, using default", name);
+            }
+        };
+        fn load_bool(problems: &mut Problems, section: &ini::Properties, out: &mut bool, name: &str) {
             if let Some(setting_str) = section.get(name) {
                 if let Ok(setting_bool) = setting_str.parse() {
                     *out = setting_bool;
                 } else {
-                    log!(logger, "{} setting invalid ({}), using default", name, setting_str);
+                    problems.add(name, setting_str, "must be true or false", out.to_string());
+                }
+            }
+        }
+        fn load_num<T: std::str::FromStr + PartialOrd + std::fmt::Display>(
+            problems: &mut Problems,
+            section: &ini::Properties,
+            out: &mut T,
+            name: &str,
+            range: std::ops::RangeInclusive<T>,
+        ) {
+            if let Some(setting_str) = section.get(name) {
+                if let Some(setting_num) = setting_str.parse().ok().filter(|n| range.contains(n)) {
+                    *out = setting_num;
+                } else {
+                    let reason = format!("must be a number from {} to {}", range.start(), range.end());
+                    problems.add(name, setting_str, reason, out.to_string());
+                }
+            }
+        }
+        fn load_list<T: std::str::FromStr + std::fmt::Display>(
+            problems: &mut Problems,
+            section: &ini::Properties,
+            out: &mut Vec<T>,
+            name: &str,
+            valid: impl Fn(&T) -> bool,
+        ) {
+            if let Some(setting_str) = section.get(name) {
+                if let Some(setting_list) = parse_list(setting_str).filter(|list: &Vec<T>| list.iter().all(&valid)) {
+                    *out = setting_list;
+                } else {
+                    let fallback = if out.is_empty() { "nothing".into() } else { out.iter().join(" ") };
+                    problems.add(name, setting_str, "invalid list", fallback);
+                }
+            }
+        }
+        fn load_gbuttons(problems: &mut Problems, section: &ini::Properties, out: &mut GButton, name: &str) {
+            if let Some(setting_str) = section.get(name) {
+                if let Some(buttons) = parse_buttons(setting_str, &ALL_GBUTTONS) {
+                    *out = buttons;
+                } else {
+                    problems.add(name, setting_str, "invalid buttons", buttons_to_string(*out, &ALL_GBUTTONS));
+                }
+            }
+        }
+        fn load_name<T: Copy + PartialEq>(
+            problems: &mut Problems,
+            section: &ini::Properties,
+            out: &mut T,
+            name: &str,
+            names: &[(&str, T)],
+        ) {
+            if let Some(setting_str) = section.get(name) {
+                if let Some((_, value)) = names.iter().copied().find(|(name, _)| *name == setting_str) {
+                    *out = value;
+                } else {
+                    let fallback = names.iter().find(|(_, value)| *value == *out).map(|(name, _)| *name);
+                    let reason = format!("must be one of {}", names.iter().map(|(name, _)| name).join(", "));
+                    problems.add(name, setting_str, reason, fallback.unwrap_or_default());
                 }
-            } else {
-                log!(logger, "{} setting not found, using default", name);
             }
         }
+        if let Some(buttons) = ini.section(Some(section::BUTTONS)) {
+            problems.section = section::BUTTONS.into();
+            for (my_map, gbut_name) in config.buttons.iter_mut().zip(GBUTTONS.iter().copied().map(|(name, _)| name)) {
+                if let Some(xbut_name) = buttons.get(gbut_name) {
+                    if let Some(mapping) = parse_buttons(xbut_name, &XBUTTONS) {
+                        *my_map = mapping;
+                    } else {
+                        problems.add(gbut_name, xbut_name, "invalid buttons", buttons_to_string(*my_map, &XBUTTONS));
+                    }
+                } else {
+                    log!(logger, "Mapping for {} button not found, using default", gbut_name);
+                }
+            }
+        } else {
+            log!(logger, "Buttons section not found, using defaults");
+        }
         if let Some(section) = ini.section(Some(section::CONTROLLER)) {
-            load_bool(logger, section, &mut config.auto_recenter, item::AUTO_RECENTER);
-            if let Some(deadzone_str) = section.get(item::DEADZONE) {
-                if let Some(deadzone_int) = deadzone_str.parse().ok().filter(|&i| i <= 100) {
-                    config.deadzone = deadzone_int;
+            problems.section = section::CONTROLLER.into();
+            let problems = &mut problems;
+            not_found(section, item::AUTO_RECENTER);
+            not_found(section, item::DEADZONE);
+            load_bool(problems, section, &mut config.auto_recenter, item::AUTO_RECENTER);
+            load_gbuttons(problems, section, &mut config.recenter_combo, item::RECENTER_COMBO);
+            load_num(problems, section, &mut config.recenter_combo_time, item::RECENTER_COMBO_TIME, 0..=60000);
+            load_bool(problems, section, &mut config.suppress_recenter_combo, item::SUPPRESS_RECENTER_COMBO);
+            load_bool(problems, section, &mut config.press_to_join, item::PRESS_TO_JOIN);
+            load_num(problems, section, &mut config.disconnect_grace_time, item::DISCONNECT_GRACE_TIME, 0..=60000);
+            load_num(problems, section, &mut config.always_connected, item::ALWAYS_CONNECTED, 0..=4);
+            load_num(problems, section, &mut config.rumble_period, item::RUMBLE_PERIOD, 0..=1000);
+            load_num(problems, section, &mut config.rumble_max_time, item::RUMBLE_MAX_TIME, 0..=60000);
+            load_bool(problems, section, &mut config.identify_on_connect, item::IDENTIFY_ON_CONNECT);
+            load_num(problems, section, &mut config.deadzone, item::DEADZONE, 0..=100);
+            if let Some(axis_str) = section.get(item::COMBINED_TRIGGERS) {
+                if axis_str == "None" {
+                    config.combined_triggers = None;
+                } else if let Some((_, axis)) = STICK_AXES.iter().copied().find(|(name, _)| *name == axis_str) {
+                    config.combined_triggers = Some(axis);
                 } else {
-                    log!(logger, "Deadzone setting invalid ({}), using default", deadzone_str);
+                    let reason =
+                        format!("must be None or one of {}", STICK_AXES.iter().map(|(name, _)| name).join(", "));
+                    problems.add(item::COMBINED_TRIGGERS, axis_str, reason, "None");
                 }
-            } else {
-                log!(logger, "Deadzone setting not found, using default");
             }
+            load_num(
+                problems,
+                section,
+                &mut config.combined_triggers_center,
+                item::COMBINED_TRIGGERS_CENTER,
+                -100..=100,
+            );
         } else {
             log!(logger, "Controller section not found, using defaults");
         }
+        for (i, port) in config.ports.iter_mut().enumerate() {
+            let section_name = section::port(i);
+            let Some(section) = ini.section(Some(section_name.as_str())) else {
+                continue;
+            };
+            problems.section = section_name;
+            let problems = &mut problems;
+            for (stick, prefix) in port.sticks_mut().into_iter().zip(STICK_PREFIXES) {
+                let key = |name| format!("{}{}", prefix, name);
+                load_num(problems, section, &mut stick.hysteresis, &key(item::HYSTERESIS), 0..=100);
+                load_num(problems, section, &mut stick.smoothing_time, &key(item::SMOOTHING_TIME), 0..=1000);
+                load_num(problems, section, &mut stick.snapback_time, &key(item::SNAPBACK_TIME), 0..=1000);
+                load_num(problems, section, &mut stick.snap_window, &key(item::SNAP_WINDOW), 0..=45);
+                load_list(problems, section, &mut stick.snap_angles, &key(item::SNAP_ANGLES), |&a| a < 360);
+                load_list(problems, section, &mut stick.notches, &key(item::NOTCHES), |a| (0.0..360.0).contains(a));
+                load_num(problems, section, &mut stick.tremor_time, &key(item::TREMOR_TIME), 0..=1000);
+            }
+            load_bool(problems, section, &mut port.enabled, item::ENABLED);
+            load_name(problems, section, &mut port.raw_mode, item::RAW_MODE, &RAW_MODES);
+            load_gbuttons(problems, section, &mut port.sticky_buttons, item::STICKY_BUTTONS);
+            load_gbuttons(problems, section, &mut port.hold_buttons, item::HOLD_BUTTONS);
+            load_num(problems, section, &mut port.min_hold_time, item::MIN_HOLD_TIME, 0..=5000);
+            load_num(problems, section, &mut port.rumble_large_weight, item::RUMBLE_LARGE_WEIGHT, 0..=100);
+            load_num(problems, section, &mut port.rumble_strength, item::RUMBLE_STRENGTH, 0..=1000);
+            load_bool(problems, section, &mut port.drift_compensation, item::DRIFT_COMPENSATION);
+            load_num(problems, section, &mut port.drift_window, item::DRIFT_WINDOW, 0..=100);
+            load_num(problems, section, &mut port.drift_idle_time, item::DRIFT_IDLE_TIME, 0..=60000);
+            load_num(problems, section, &mut port.drift_max_offset, item::DRIFT_MAX_OFFSET, 0..=100);
+            let mut calibrated = false;
+            load_bool(problems, section, &mut calibrated, item::CALIBRATED);
+            port.calibration = calibrated.then(|| {
+                let mut calibration = Calibration::default();
+                for (stick, prefix) in calibration.sticks_mut().into_iter().zip(STICK_PREFIXES) {
+                    let key = |name| format!("{}{}", prefix, name);
+                    let mut center = Vec::new();
+                    load_list(problems, section, &mut center, &key(item::CENTER), |_: &i16| true);
+                    match center[..] {
+                        [x, y] => stick.center = (x, y),
+                        [] => (),
+                        _ => problems.add(&key(item::CENTER), center.iter().join(" "), "needs 2 values", "0 0"),
+                    }
+                    load_list(problems, section, &mut stick.gate, &key(item::GATE), |_: &f64| true);
+                    if !matches!(stick.gate.len(), 0 | CALIBRATION_SAMPLES) {
+                        let reason = format!("needs {} values", CALIBRATION_SAMPLES);
+                        problems.add(&key(item::GATE), stick.gate.iter().join(" "), reason, "nothing");
+                        stick.gate.clear();
+                    } else if !stick::valid_gate(&stick.gate) {
+                        problems.add(&key(item::GATE), stick.gate.iter().join(" "), gate_range_reason(), "nothing");
+                        stick.gate.clear();
+                    }
+                }
+                let mut triggers = Vec::new();
+                load_list(problems, section, &mut triggers, item::TRIGGER_RANGE, |_: &u8| true);
+                match triggers[..] {
+                    [] => (),
+                    [l_min, l_max, r_min, r_max] if l_min < l_max && r_min < r_max => {
+                        calibration.triggers = Some([(l_min, l_max), (r_min, r_max)])
+                    },
+                    _ => problems.add(
+                        item::TRIGGER_RANGE,
+                        triggers.iter().join(" "),
+                        "needs a resting and pressed value for each trigger",
+                        "nothing",
+                    ),
+                }
+                calibration
+            });
+        }
+        let global_buttons = config.buttons;
+        for (i, slot) in config.slots.iter_mut().enumerate() {
+            let section_name = section::slot(i);
+            let Some(section) = ini.section(Some(section_name.as_str())) else {
+                continue;
+            };
+            problems.section = section_name;
+            let mut ports = Vec::new();
+            load_list(&mut problems, section, &mut ports, item::PORTS, |port: &usize| (1..=4).contains(port));
+            if !ports.is_empty() {
+                slot.ports = ports.iter().map(|port| port - 1).collect();
+            }
+            load_name(&mut problems, section, &mut slot.stick_merge, item::STICK_MERGE, &STICK_MERGES);
+            // a mapping profile only exists if the section has any mappings in it
+            if GBUTTONS.iter().any(|(name, _)| section.contains_key(name)) {
+                let mut buttons = global_buttons;
+                for (my_map, (gbut_name, _)) in buttons.iter_mut().zip(GBUTTONS) {
+                    if let Some(xbut_name) = section.get(gbut_name) {
+                        if let Some(mapping) = parse_buttons(xbut_name, &XBUTTONS) {
+                            *my_map = mapping;
+                        } else {
+                            let fallback = buttons_to_string(*my_map, &XBUTTONS);
+                            problems.add(gbut_name, xbut_name, "invalid buttons", fallback);
+                        }
+                    }
+                }
+                slot.buttons = Some(buttons);
+            }
+        }
         if let Some(section) = ini.section(Some(section::APPLICATION)) {
-            load_bool(logger, section, &mut config.close_to_tray, item::CLOSE_TO_TRAY);
+            problems.section = section::APPLICATION.into();
+            not_found(section, item::CLOSE_TO_TRAY);
+            load_bool(&mut problems, section, &mut config.close_to_tray, item::CLOSE_TO_TRAY);
         } else {
             log!(logger, "Application section not found, using defaults");
         }
-        log!(logger, "Settings loaded from {}.", CONFIG_PATH);
-        config
+        log!(logger, "Settings loaded from {}.", path.display());
+        Loaded { config, problems: problems.list, unreadable: false }
     }
 
-    pub fn save(&self, logger: &impl Fn(&str)) -> bool {
-        let mut ini = ini::Ini::new();
-        for (gc, xb) in self.buttons.iter().copied().enumerate() {
-            ini.entry(Some(section::BUTTONS.into()))
-                .or_insert_with(Default::default)
-                .insert(GBUTTONS[gc].0, XBUTTONS[xb].0);
+    pub fn save(&self, path: &Path, logger: &impl Fn(&str)) -> bool {
+        let text = match toml::to_string(self) {
+            Ok(text) => text,
+            Err(e) => {
+                log!(logger, "Failed to save settings: {}", e);
+                return false;
+            },
+        };
+        if let Some(dir) = path.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            log!(logger, "Failed to create {}: {}", dir.display(), e);
+            return false;
         }
-        ini.with_section(Some(section::CONTROLLER))
-            .set(item::AUTO_RECENTER, self.auto_recenter.to_string())
-            .set(item::DEADZONE, self.deadzone.to_string());
-        ini.with_section(Some(section::APPLICATION)).set(item::CLOSE_TO_TRAY, self.close_to_tray.to_string());
-        match ini.write_to_file(CONFIG_PATH) {
+        // write to a separate file first, so the settings file is never left half written
+        let temp_path = path.with_extension("toml.tmp");
+        match std::fs::write(&temp_path, text).and_then(|()| std::fs::rename(&temp_path, path)) {
             Ok(()) => {
-                log!(logger, "Settings saved to {}.", CONFIG_PATH);
+                log!(logger, "Settings saved to {}.", path.display());
                 true
             },
             Err(e) => {
@@ -193,3 +1275,221 @@ impl Config {
         }
     }
 }
+
+/// Notices when the settings file changes, by polling its size and modification time.
+pub struct FileWatcher {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    /// When the file last changed, if it hasn't been reported yet.
+    changed_at: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let stamp = Self::stamp(&path);
+        Self { path, stamp, changed_at: None }
+    }
+
+    fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// Whether the file has changed, and then stayed the same for `RELOAD_DEBOUNCE`. A deleted file doesn't count.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let stamp = Self::stamp(&self.path);
+        if stamp != self.stamp {
+            self.stamp = stamp;
+            self.changed_at = Some(now);
+            return false;
+        }
+        match self.changed_at {
+            Some(changed_at) if stamp.is_some() && now - changed_at >= RELOAD_DEBOUNCE => {
+                self.changed_at = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An empty folder to keep a test's files in, removed once the test is done with it.
+    pub(crate) struct TestDir(PathBuf);
+
+    impl TestDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("gc-x-test-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn button_mappings() {
+        assert!(parse_buttons("LB+RB", &XBUTTONS) == Some(XButton::LEFT_SHOULDER | XButton::RIGHT_SHOULDER));
+        assert!(parse_buttons(" A + B ", &XBUTTONS) == Some(XButton::A | XButton::B));
+        assert!(parse_buttons(UNMAPPED, &XBUTTONS) == Some(XButton::empty()));
+        assert!(parse_buttons("A+Nope", &XBUTTONS).is_none());
+        assert!(parse_buttons("", &XBUTTONS).is_none());
+        let buttons = XButton::DPAD_UP | XButton::A;
+        assert_eq!(buttons_to_string(buttons, &XBUTTONS), "A+Up");
+        assert!(parse_buttons(&buttons_to_string(buttons, &XBUTTONS), &XBUTTONS) == Some(buttons));
+        assert_eq!(buttons_to_string(XButton::empty(), &XBUTTONS), UNMAPPED);
+    }
+
+    #[test]
+    fn locates_problems() {
+        let text = "deadzone = 500\n[[ports]]\n[[ports]]\nenabled = 3\n[ports.main_stick]\nhysteresis = -1\n";
+        let at = |pattern: &str| locate(text, text.find(pattern).unwrap());
+        let expect = |section: &str, key: &str, value: &str| (section.into(), key.into(), value.into());
+        assert_eq!(at("500"), expect("", "deadzone", "500"));
+        assert_eq!(at("enabled"), expect("ports[2]", "enabled", "3"));
+        assert_eq!(at("-1"), expect("ports[2].main_stick", "hysteresis", "-1"));
+        assert_eq!(at("[ports.main"), expect("ports[2].main_stick", "", ""));
+    }
+
+    #[test]
+    fn file_watcher_waits_for_writes_to_finish() {
+        let dir = TestDir::new("watch");
+        let path = dir.join(CONFIG_FILE);
+        std::fs::write(&path, "deadzone = 5\n").unwrap();
+        let mut watcher = FileWatcher::new(path.clone());
+        let now = Instant::now();
+        assert!(!watcher.poll(now));
+        std::fs::write(&path, "deadzone = 10\n").unwrap();
+        assert!(!watcher.poll(now));
+        assert!(!watcher.poll(now + RELOAD_DEBOUNCE / 2));
+        assert!(watcher.poll(now + RELOAD_DEBOUNCE));
+        assert!(!watcher.poll(now + RELOAD_DEBOUNCE * 2));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.poll(now + RELOAD_DEBOUNCE * 3));
+        assert!(!watcher.poll(now + RELOAD_DEBOUNCE * 4));
+    }
+
+    #[test]
+    fn migrates_ini() {
+        let dir = TestDir::new("migrate");
+        std::fs::write(
+            dir.join(LEGACY_CONFIG_FILE),
+            "[Buttons]\nA=B\nB=A\n\
+            [Controller]\nAutoRecenter=false\nDeadzone=12\n\
+            [Application]\nCloseToTray=true\n\
+            [Port2]\nMainHysteresis=3\nEnabled=false\nCalibrated=true\nCCenter=100 -50\n",
+        )
+        .unwrap();
+        let path = dir.join(CONFIG_FILE);
+        let loaded = Config::load(&path, &|_| ());
+        assert!(loaded.problems.is_empty());
+        let config = loaded.config;
+        assert!(config.buttons[0] == XButton::B && config.buttons[1] == XButton::A);
+        assert!(!config.auto_recenter);
+        assert_eq!(config.deadzone, 12);
+        assert!(config.close_to_tray);
+        assert_eq!(config.ports[1].main_stick.hysteresis, 3);
+        assert!(!config.ports[1].enabled);
+        let calibration = config.ports[1].calibration.as_ref().unwrap();
+        assert_eq!(calibration.c_stick.center, (100, -50));
+        assert!(calibration.main_stick.gate.is_empty());
+        // the migrated settings are saved in the new format, and the old file is kept
+        assert!(dir.join(LEGACY_CONFIG_FILE).exists());
+        let reloaded = Config::load(&path, &|_| ());
+        assert!(reloaded.problems.is_empty());
+        assert!(reloaded.config == config);
+    }
+
+    #[test]
+    fn migration_reports_bad_settings() {
+        let dir = TestDir::new("migrate-bad");
+        std::fs::write(dir.join(LEGACY_CONFIG_FILE), "[Controller]\nDeadzone=150\nAutoRecenter=maybe\n").unwrap();
+        let path = dir.join(CONFIG_FILE);
+        let loaded = Config::load(&path, &|_| ());
+        let keys: Vec<_> = loaded.problems.iter().map(|problem| problem.key.as_str()).collect();
+        assert_eq!(keys, [item::AUTO_RECENTER, item::DEADZONE]);
+        assert_eq!(loaded.config.deadzone, Config::default().deadzone);
+        // nothing is saved until the problems are looked at
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut config = Config { deadzone: 20, combined_triggers_center: -30, ..Default::default() };
+        config.ports[2].c_stick.snap_window = 10;
+        config.slots[1].ports = vec![1, 2];
+        let text = toml::to_string(&config).unwrap();
+        let path = PathBuf::from(CONFIG_FILE);
+        let mut problems = Problems::new(&path);
+        assert!(Config::from_toml(&text, &mut problems).unwrap() == config);
+        assert!(problems.list.is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_left_out() {
+        let path = PathBuf::from(CONFIG_FILE);
+        let mut problems = Problems::new(&path);
+        let text = "version = 1\ndeadzone = 12\nclose_to_tray = maybe\nnot a setting\n";
+        let config = Config::from_toml(text, &mut problems).unwrap();
+        assert_eq!(config.deadzone, 12);
+        let keys: Vec<_> = problems.list.iter().map(|problem| problem.key.as_str()).collect();
+        assert_eq!(keys, ["close_to_tray", ""]);
+    }
+
+    #[test]
+    fn slot_mappings_follow_the_global_one() {
+        let path = PathBuf::from(CONFIG_FILE);
+        let mut problems = Problems::new(&path);
+        let text = "version = 1\n[buttons]\nA = \"X\"\n[[slots]]\n[slots.buttons]\nB = \"Y\"\n";
+        let config = Config::from_toml(text, &mut problems).unwrap();
+        assert!(problems.list.is_empty());
+        let slot_buttons = config.slots[0].buttons.unwrap();
+        assert!(slot_buttons[0] == XButton::X && slot_buttons[1] == XButton::Y);
+        assert!(slot_buttons[2..] == Config::default().buttons[2..]);
+    }
+
+    #[test]
+    fn reload_keeps_settings_over_a_bad_file() {
+        let dir = TestDir::new("reload");
+        let path = dir.join(CONFIG_FILE);
+        let reload = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            Config::reload(&path)
+        };
+        assert!(!reload("").problems.is_empty());
+        assert!(!reload("version = 1\ndeadzone = 12\nclose_to_tray = maybe\n").problems.is_empty());
+        let loaded = reload("version = 1\ndeadzone = 500\n");
+        assert!(!loaded.problems.is_empty());
+        assert!(loaded.problems.iter().all(|problem| problem.fallback == "the current settings"));
+        let loaded = reload("version = 1\ndeadzone = 12\n");
+        assert!(loaded.problems.is_empty());
+        assert_eq!(loaded.config.deadzone, 12);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let dir = TestDir::new("newer");
+        let path = dir.join(CONFIG_FILE);
+        std::fs::write(&path, format!("version = {}\ndeadzone = 12\n", CONFIG_VERSION + 1)).unwrap();
+        let loaded = Config::load(&path, &|_| ());
+        assert!(loaded.unreadable);
+        assert_eq!(loaded.problems.len(), 1);
+        assert_eq!(loaded.config.deadzone, Config::default().deadzone);
+    }
+}
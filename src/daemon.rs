@@ -1,13 +1,20 @@
 use crate::{
     adapter::GCAdapterWaiter,
-    config::{self, Config, GButton, XButton},
-    log, ui,
+    assist::ButtonAssistState,
+    calibration::{Calibrator, Progress},
+    config::{self, Config, GButton, RawMode, StickAxis, StickMerge, XButton},
+    log,
+    rumble::{self, DutyCycle, Pulses, RumbleTimeout},
+    stick::{self, DriftState, GateRecorder, NotchLearner, RawDetector, StickFilterState, TriggerRecorder},
+    ui,
 };
+use itertools::Itertools;
 use native_windows_gui as nwg;
 use parking_lot::{Mutex, Once};
 use std::{
-    f64::consts::{PI, TAU},
-    sync::Arc,
+    f64::consts::TAU,
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
 };
 use vigem::{Target, UsbReport};
 
@@ -22,29 +29,124 @@ pub struct Daemon {
     config: Arc<Mutex<Config>>,
     must_center: Arc<Mutex<[bool; 4]>>,
     joy_connected: Arc<Mutex<[bool; 4]>>,
+    requests: Arc<Mutex<[PortRequests; 4]>>,
     join_sender: nwg::NoticeSender,
     leave_sender: nwg::NoticeSender,
 }
 
-// Maths functions and controller constants graciously lifted from Dolphin
-fn square_radius_at_angle(angle: f64) -> f64 {
-    let section_angle = TAU / 4.0;
-    1.0 / (((angle + section_angle / 2.0) % section_angle) - section_angle / 2.0).cos()
+/// What the UI and command line have asked the daemon to do with a port, and what it reports back.
+#[derive(Default)]
+pub struct PortRequests {
+    /// Learn where the sticks' notches are, until this is cleared.
+    pub notch_learning: bool,
+    /// Store the current centers and the ranges recorded so far as the port's calibration.
+    pub store_calibration: bool,
+    /// Run the guided calibration routine. The daemon clears this when it ends.
+    pub calibrate: bool,
+    /// How many times to pulse the port's rumble. The daemon clears this once it's been handled.
+    pub identify: u8,
+    /// The player number of the first virtual controller the port feeds.
+    pub player: Option<u8>,
 }
 
-fn octagon_radius_at_angle(angle: f64) -> f64 {
-    let sides = 8.0;
-    let sum_int_angles = (sides - 2.0) * PI;
-    let half_int_angle = sum_int_angles / sides / 2.0;
+// Rough guess
+const TRIGGER_DEADZONE: f64 = 0.16;
+// Calibrated triggers rest at 0, so only a little is needed to hide noise
+const CALIBRATED_TRIGGER_DEADZONE: f64 = 0.05;
+// How long a request to identify a port waits for a controller to show up in it
+const IDENTIFY_WAIT: Duration = Duration::from_secs(1);
 
-    let angle = angle % (TAU / sides);
-    1.0 / (PI - angle - half_int_angle).sin() * half_int_angle.sin()
+/// Merge both triggers into a single axis, for games that expect them to share one.
+/// The right trigger pulls towards the positive end and the left trigger towards the negative end.
+/// `center` is where the axis rests when neither trigger is pressed, in % of the axis range.
+fn combine_triggers(left: u8, right: u8, center: i8) -> i16 {
+    let center = f64::from(center) / 100.0;
+    let left = f64::from(left) / f64::from(u8::MAX);
+    let right = f64::from(right) / f64::from(u8::MAX);
+    let value = center + right * (1.0 - center) - left * (1.0 + center);
+    (value.clamp(-1.0, 1.0) * f64::from(i16::MAX)) as i16
 }
-const MAIN_STICK_GATE_RADIUS: f64 = 0.7937125;
-const C_STICK_GATE_RADIUS: f64 = 0.7221375;
 
-// Rough guess
-const TRIGGER_DEADZONE: f64 = 0.16;
+/// State kept for each port while a controller is connected to it.
+#[derive(Default)]
+struct PortState {
+    center: ((i16, i16), (i16, i16)),
+    filters: [StickFilterState; 2],
+    assists: ButtonAssistState,
+    drifts: [DriftState; 2],
+    learner: Option<[NotchLearner; 2]>,
+    /// How far the sticks and triggers have gone since they were last centered.
+    gates: [GateRecorder; 2],
+    triggers: [TriggerRecorder; 2],
+    calibrator: Option<Calibrator>,
+    /// Whether inputs are passed through without deadzone, scaling or recentering.
+    raw: bool,
+    /// Decides whether to switch to raw mode, until that's clear.
+    raw_detector: Option<RawDetector>,
+    /// When the recenter combo started being held, and whether it has recentered yet.
+    combo: Option<(Instant, bool)>,
+    /// Short rumbles to confirm something happened or identify the controller, overriding the game's rumble.
+    pulses: Option<Pulses>,
+    rumble: DutyCycle,
+    rumble_timeout: RumbleTimeout,
+    /// When the controller stopped responding, if it's still within the disconnect grace time.
+    lost_since: Option<Instant>,
+    /// When a request to identify the port started waiting for a controller.
+    identify_since: Option<Instant>,
+    /// Whether a controller has been seen in this port while it's disabled.
+    ignored: bool,
+}
+
+/// A controller's inputs after processing, ready to be sent to a virtual controller.
+#[derive(Clone, Copy, Default)]
+struct PadOutput {
+    /// The GC buttons pressed, for virtual controllers with their own mapping.
+    gc_buttons: GButton,
+    report: UsbReport,
+}
+
+/// Combine the reports of several controllers feeding the same virtual controller.
+/// Buttons are pressed if they're pressed on any of them, and sticks and triggers are merged by `merge`.
+fn merge_reports(reports: &[UsbReport], merge: StickMerge) -> UsbReport {
+    let pick = |magnitude: fn(&UsbReport) -> f64| match merge {
+        StickMerge::Largest => reports.iter().max_by(|a, b| magnitude(a).total_cmp(&magnitude(b))),
+        StickMerge::Primary => reports.iter().find(|report| magnitude(report) > 0.0).or(reports.first()),
+    };
+    let mut merged =
+        UsbReport { buttons: reports.iter().fold(0, |buttons, report| buttons | report.buttons), ..Default::default() };
+    if let Some(report) = pick(|report| f64::from(report.left_x).hypot(report.left_y.into())) {
+        (merged.left_x, merged.left_y) = (report.left_x, report.left_y);
+    }
+    if let Some(report) = pick(|report| f64::from(report.right_x).hypot(report.right_y.into())) {
+        (merged.right_x, merged.right_y) = (report.right_x, report.right_y);
+    }
+    if let Some(report) = pick(|report| report.left_trigger.into()) {
+        merged.left_trigger = report.left_trigger;
+    }
+    if let Some(report) = pick(|report| report.right_trigger.into()) {
+        merged.right_trigger = report.right_trigger;
+    }
+    merged
+}
+
+fn map_buttons(mapping: &[XButton; config::GBUTTONS.len()], gbuttons: GButton) -> XButton {
+    let mut buttons = XButton::empty();
+    for (gc, xb) in mapping.iter().enumerate() {
+        if gbuttons.contains(config::GBUTTONS[gc].1) {
+            buttons.insert(*xb);
+        }
+    }
+    buttons
+}
+
+fn axis_mut(report: &mut UsbReport, axis: StickAxis) -> &mut i16 {
+    match axis {
+        StickAxis::LeftX => &mut report.left_x,
+        StickAxis::LeftY => &mut report.left_y,
+        StickAxis::RightX => &mut report.right_x,
+        StickAxis::RightY => &mut report.right_y,
+    }
+}
 
 impl Daemon {
     pub fn new(
@@ -53,6 +155,7 @@ impl Daemon {
         config: Arc<Mutex<Config>>,
         must_center: Arc<Mutex<[bool; 4]>>,
         joy_connected: Arc<Mutex<[bool; 4]>>,
+        requests: Arc<Mutex<[PortRequests; 4]>>,
         join_sender: nwg::NoticeSender,
         leave_sender: nwg::NoticeSender,
         exit_sender: nwg::NoticeSender,
@@ -83,99 +186,278 @@ impl Daemon {
             }
             return Err(());
         }
-        Ok(Self { exit_once, waiter, vigem, logger, config, must_center, joy_connected, join_sender, leave_sender })
+        Ok(Self {
+            exit_once,
+            waiter,
+            vigem,
+            logger,
+            config,
+            must_center,
+            joy_connected,
+            requests,
+            join_sender,
+            leave_sender,
+        })
     }
 
     pub fn run(&mut self) {
-        let targets = Arc::new(Mutex::new([None, None, None, None]));
+        let mut targets: [Option<Target>; 4] = [None, None, None, None];
         let mut notif_handles = [None, None, None, None];
-        let rumbles = Arc::new(Mutex::new([0; 4]));
-        let mut centers: [((i16, i16), (i16, i16)); 4] = Default::default();
+        // the motor speeds each virtual controller has asked for
+        let mut rumbles = [(0, 0); 4];
+        // rumble notifications arrive on a driver thread, so they're passed along as messages tagged with
+        // the virtual controller and which of its targets sent them, so stale ones can be told apart
+        let (rumble_sender, rumble_receiver) = mpsc::channel::<(usize, u32, (u8, u8))>();
+        let mut generations = [0u32; 4];
+        let mut sent_rumble = [0; 4];
+        // the virtual controller each port has joined, when they join by pressing A
+        let mut joined: [Option<usize>; 4] = [None; 4];
+        // ports still holding the A press they joined with, which isn't passed on to the game
+        let mut join_held = [false; 4];
+        // the XInput player number of each virtual controller
+        let mut player_numbers: [Option<u8>; 4] = [None; 4];
+        let mut port_states: [PortState; 4] = Default::default();
 
         let transform = |ax| ((i16::from(ax) - 0x80) << 8) + i16::from(ax);
 
-        'outer: loop {
-            let pads = self.waiter.get_pads();
+        let mut first_frame = true;
+        loop {
+            // the first frame doesn't wait for the adapter, so that virtual controllers that are always connected
+            // show up straight away
+            let fake_frame = std::mem::take(&mut first_frame);
+            let pads = if fake_frame { Some(Default::default()) } else { self.waiter.get_pads() };
             if self.exit_once.state().done() {
                 break;
             }
-            for (i, pad_opt, target_opt, notif, center, must_center, connected) in itertools::izip!(
+            // the controllers can't come back without the adapter, so there's no point waiting for them
+            let adapter_lost = pads.is_none();
+            let pads = pads.unwrap_or_default();
+            let now = Instant::now();
+            let mut outputs: [Option<PadOutput>; 4] = Default::default();
+            let mut requests = self.requests.lock();
+            for (i, pad_opt, output, state, must_center, request, connected) in itertools::izip!(
                 0..,
                 &pads,
-                targets.lock().iter_mut(),
-                &mut notif_handles,
-                &mut centers,
+                &mut outputs,
+                &mut port_states,
                 self.must_center.lock().iter_mut(),
+                requests.iter_mut(),
                 self.joy_connected.lock().iter_mut()
             ) {
-                match (pad_opt, target_opt.as_mut()) {
-                    (Some(_), None) => {
+                let pad_opt = if self.config.lock().ports[i].enabled {
+                    state.ignored = false;
+                    pad_opt
+                } else {
+                    if pad_opt.is_some() && !state.ignored {
+                        log!(self.logger, "Ignoring the GC controller in port {}, the port is disabled.", i + 1);
+                        state.ignored = true;
+                    }
+                    &None
+                };
+                match (pad_opt, *connected) {
+                    (Some(_), false) => {
                         log!(self.logger, "New GC controller connected in port {}!", i + 1);
-                        *center = ((0, 0), (0, 0));
-                        *must_center = self.config.lock().auto_recenter;
+                        // notches learned so far are kept, so learning can go on if the controller dropped out
+                        *state = PortState { learner: state.learner.take(), ..Default::default() };
+                        let config = self.config.lock();
+                        state.raw = config.ports[i].raw_mode == RawMode::On;
+                        if config.ports[i].raw_mode == RawMode::Auto {
+                            state.raw_detector = Some(Default::default());
+                        }
+                        if state.raw {
+                            log!(
+                                self.logger,
+                                "Using raw mode for P{}, sticks and triggers are passed through as-is",
+                                i + 1
+                            );
+                        } else {
+                            if let Some(calibration) = &config.ports[i].calibration {
+                                state.center = (calibration.main_stick.center, calibration.c_stick.center);
+                                log!(self.logger, "Applied saved calibration for P{}", i + 1);
+                            }
+                            *must_center = config.auto_recenter;
+                        }
+                        drop(config);
                         *connected = true;
                         self.join_sender.notice();
-                        let mut target = Target::new();
-                        if let Err(e) = self.vigem.add_target(&mut target) {
-                            log!(self.logger, "Could not add target: {}", e);
-                            continue 'outer;
-                        }
-
-                        let rumbles = rumbles.clone();
-                        let targets = targets.clone();
-                        let waiter = &self.waiter;
-                        *notif = match target.register_notification(
-                            move |target: &Target, large_motor: u8, small_motor: u8| {
-                                let rumble =
-                                    (u16::from(large_motor) * 0x55 + u16::from(small_motor) * (0x100 - 0x55)) > 0x800;
-                                let i = targets.lock().iter().position(|tg: &Option<Target>| {
-                                    tg.as_ref().map(|tg| tg.index() == target.index()).unwrap_or(false)
-                                });
-                                if let Some(i) = i {
-                                    let mut rumbles = rumbles.lock();
-                                    rumbles[i] = rumble.into();
-                                    waiter.send_rumble(rumbles.clone());
-                                }
-                            },
-                        ) {
-                            Ok(handle) => Some(handle),
-                            Err(e) => {
-                                log!(self.logger, "Could not register rumble notification: {}", e);
-                                None
-                            },
-                        };
-                        *target_opt = Some(target);
                     },
-                    (None, Some(target)) => {
-                        log!(self.logger, "GC controller in port {} disconnected.", i + 1);
-                        if let Err(e) = self.vigem.remove_target(target) {
-                            log!(self.logger, "Failed to remove target: {}", e);
+                    (Some(_), true) => state.lost_since = None,
+                    (None, true) => {
+                        let grace_time = Duration::from_millis(self.config.lock().disconnect_grace_time.into());
+                        if adapter_lost || now - *state.lost_since.get_or_insert(now) >= grace_time {
+                            log!(self.logger, "GC controller in port {} disconnected.", i + 1);
+                            *connected = false;
+                            self.leave_sender.notice();
+                        } else {
+                            // keep its virtual controllers around in case it comes back
+                            *output = Some(Default::default());
                         }
-                        *target_opt = None;
-                        *notif = None;
-                        *connected = false;
-                        self.leave_sender.notice();
                     },
-                    _ => (),
+                    (None, false) => (),
                 }
-                if let (Some(pad), Some(target)) = (pad_opt.as_ref(), target_opt.as_mut()) {
-                    let mut buttons = XButton::empty();
-                    for (gc, xb) in self.config.lock().buttons.iter().enumerate() {
-                        if pad.buttons.contains(config::GBUTTONS[gc].1) {
-                            buttons.insert(config::XBUTTONS[*xb].1);
+                if let Some(pad) = pad_opt.as_ref() {
+                    let mut gbuttons = pad.buttons;
+                    let (combo, combo_time, suppress_combo) = {
+                        let config = self.config.lock();
+                        (config.recenter_combo, config.recenter_combo_time, config.suppress_recenter_combo)
+                    };
+                    if !combo.is_empty() && gbuttons.contains(combo) {
+                        let (since, done) = state.combo.get_or_insert((now, false));
+                        if !*done && now - *since >= Duration::from_millis(combo_time.into()) {
+                            *must_center = true;
+                            *done = true;
+                            state.pulses = Some(Pulses::new(1, now));
+                        }
+                        if suppress_combo {
+                            gbuttons.remove(combo);
+                        }
+                    } else {
+                        state.combo = None;
+                    }
+                    gbuttons = state.assists.apply(&self.config.lock().ports[i], gbuttons, now);
+
+                    let buttons = map_buttons(&self.config.lock().buttons, gbuttons);
+
+                    if let Some(detector) = &mut state.raw_detector
+                        && let Some(digital) = detector.update((pad.stick_x, pad.stick_y), now)
+                    {
+                        state.raw_detector = None;
+                        if digital {
+                            log!(self.logger, "P{} looks like a digital controller, switching to raw mode", i + 1);
+                            state.raw = true;
+                            state.center = Default::default();
+                            state.filters = Default::default();
                         }
                     }
 
-                    if *must_center {
-                        *center = (
+                    if *must_center && state.raw {
+                        *must_center = false;
+                        log!(self.logger, "P{} is in raw mode, not recentering", i + 1);
+                    } else if *must_center {
+                        state.center = (
                             (transform(pad.stick_x), transform(pad.stick_y)),
                             (transform(pad.cstick_x), transform(pad.cstick_y)),
                         );
+                        state.gates = Default::default();
                         *must_center = false;
                         log!(self.logger, "Joysticks centered for P{}", i + 1);
                     }
 
-                    let deadstick = |ax, center: i16| match transform(ax) - center {
+                    let main_raw = (transform(pad.stick_x), transform(pad.stick_y));
+                    let c_raw = (transform(pad.cstick_x), transform(pad.cstick_y));
+
+                    if request.calibrate {
+                        let calibrator = state.calibrator.get_or_insert_with(|| {
+                            log!(self.logger, "Calibrating P{}.", i + 1);
+                            Calibrator::default()
+                        });
+                        match calibrator.update([main_raw, c_raw], [pad.trigger_left, pad.trigger_right], now) {
+                            Progress::Prompt(text) => log!(self.logger, "P{}: {}", i + 1, text),
+                            Progress::Continue => (),
+                            Progress::Done(calibration) => {
+                                log!(self.logger, "P{} calibrated. Save settings to keep the calibration.", i + 1);
+                                state.center = (calibration.main_stick.center, calibration.c_stick.center);
+                                state.gates = Default::default();
+                                self.config.lock().ports[i].calibration = Some(calibration);
+                                state.calibrator = None;
+                                request.calibrate = false;
+                            },
+                            Progress::Failed(reason) => {
+                                log!(self.logger, "Calibrating P{} failed: {}", i + 1, reason);
+                                state.calibrator = None;
+                                request.calibrate = false;
+                            },
+                        }
+                        // don't let the calibration motions reach the game
+                        *output = Some(Default::default());
+                        continue;
+                    } else if state.calibrator.take().is_some() {
+                        log!(self.logger, "Calibration cancelled for P{}.", i + 1);
+                    }
+
+                    let port_config = self.config.lock().ports[i].clone();
+                    if !state.raw {
+                        state.drifts[0].apply(&port_config, main_raw, &mut state.center.0, now);
+                        state.drifts[1].apply(&port_config, c_raw, &mut state.center.1, now);
+                    }
+                    let centered = |raw: (i16, i16), center: (i16, i16)| {
+                        (raw.0.saturating_sub(center.0), raw.1.saturating_sub(center.1))
+                    };
+                    state.gates[0].record(centered(main_raw, state.center.0));
+                    state.gates[1].record(centered(c_raw, state.center.1));
+                    state.triggers[0].record(pad.trigger_left);
+                    state.triggers[1].record(pad.trigger_right);
+                    let main_stick =
+                        state.filters[0].apply(&port_config.main_stick, centered(main_raw, state.center.0), now);
+                    let c_stick = state.filters[1].apply(&port_config.c_stick, centered(c_raw, state.center.1), now);
+
+                    if request.store_calibration {
+                        request.store_calibration = false;
+                        let mut config = self.config.lock();
+                        let calibration = config.ports[i].calibration.get_or_insert_with(Default::default);
+                        calibration.main_stick.center = state.center.0;
+                        calibration.c_stick.center = state.center.1;
+                        for (stick, gate, name) in
+                            itertools::izip!(calibration.sticks_mut(), &state.gates, ["Main stick", "C-stick"])
+                        {
+                            if let Some(gate) = gate.finish() {
+                                stick.gate = gate;
+                            } else {
+                                log!(
+                                    self.logger,
+                                    "P{} {} hasn't been moved around its whole gate, keeping its previous range.",
+                                    i + 1,
+                                    name
+                                );
+                            }
+                        }
+                        if let (Some(left), Some(right)) = (state.triggers[0].finish(), state.triggers[1].finish()) {
+                            calibration.triggers = Some([left, right]);
+                        } else {
+                            log!(
+                                self.logger,
+                                "P{} triggers haven't been pressed all the way, keeping their previous range.",
+                                i + 1
+                            );
+                        }
+                        log!(self.logger, "Calibration stored for P{}. Save settings to keep it.", i + 1);
+                    }
+
+                    match (request.notch_learning, state.learner.as_mut()) {
+                        (true, None) => {
+                            log!(
+                                self.logger,
+                                "Learning notches for P{}, push each stick into all of its notches.",
+                                i + 1
+                            );
+                            state.learner = Some([NotchLearner::default(), NotchLearner::default()]);
+                        },
+                        (true, Some(learner)) => {
+                            learner[0].record(main_stick);
+                            learner[1].record(c_stick);
+                        },
+                        (false, Some(_)) => {
+                            let mut config = self.config.lock();
+                            for (stick, learner, name) in itertools::izip!(
+                                config.ports[i].sticks_mut(),
+                                state.learner.take().unwrap().iter(),
+                                ["Main stick", "C-stick"]
+                            ) {
+                                stick.notches = learner.finish(&stick.snap_angles);
+                                log!(
+                                    self.logger,
+                                    "P{} {} notches: {}",
+                                    i + 1,
+                                    name,
+                                    stick.notches.iter().map(|a| format!("{:.1}", a)).join(" ")
+                                );
+                            }
+                            log!(self.logger, "Save settings to keep the learned notches.");
+                        },
+                        (false, None) => (),
+                    }
+
+                    let deadstick = |ax: i16| match ax {
                         ax if ax.abs()
                             < (f64::from(i16::MAX) * f64::from(self.config.lock().deadzone) / 100.0) as _ =>
                         {
@@ -184,38 +466,350 @@ impl Daemon {
                         ax => ax,
                     };
 
-                    let scale = |x, y, center: (i16, i16), radius: f64| -> (i16, i16) {
-                        let deaded = (deadstick(x, center.0), deadstick(y, center.1));
-                        let angle = f64::from(deaded.1).atan2(deaded.1.into());
-                        let factor = square_radius_at_angle(angle) / (octagon_radius_at_angle(angle) * radius);
-                        ((f64::from(deaded.0) * factor) as _, (f64::from(deaded.1) * factor) as _)
+                    let raw = state.raw;
+                    let scale = |(x, y): (i16, i16), stick: &config::StickConfig, gate: &[f64], radius: f64| {
+                        if raw {
+                            return (x, y);
+                        }
+                        let deaded = stick::snap_angle((deadstick(x), deadstick(y)), stick);
+                        let angle = f64::from(deaded.1).atan2(deaded.0.into()).rem_euclid(TAU);
+                        // a gate that doesn't reach out from the center can't be scaled to, so it's treated as missing
+                        let gate_radius = Some(gate)
+                            .filter(|gate| !gate.is_empty())
+                            .map(|gate| stick::calibrated_radius_at_angle(gate, angle))
+                            .filter(|&r| r > 0.0)
+                            .unwrap_or_else(|| stick::octagon_radius_at_angle(angle) * radius);
+                        let factor = stick::square_radius_at_angle(angle) / gate_radius;
+                        ((f64::from(deaded.0) * factor) as i16, (f64::from(deaded.1) * factor) as i16)
                     };
 
-                    let (left_x, left_y) = scale(pad.stick_x, pad.stick_y, center.0, MAIN_STICK_GATE_RADIUS);
-                    let (right_x, right_y) = scale(pad.cstick_x, pad.cstick_y, center.1, C_STICK_GATE_RADIUS);
+                    let calibration = port_config.calibration.as_ref();
+                    let (left_x, left_y) = scale(
+                        main_stick,
+                        &port_config.main_stick,
+                        calibration.map(|c| &c.main_stick.gate[..]).unwrap_or_default(),
+                        stick::MAIN_STICK_GATE_RADIUS,
+                    );
+                    let (right_x, right_y) = scale(
+                        c_stick,
+                        &port_config.c_stick,
+                        calibration.map(|c| &c.c_stick.gate[..]).unwrap_or_default(),
+                        stick::C_STICK_GATE_RADIUS,
+                    );
 
-                    let scale_trigger = |t: u8, b| {
-                        if pad.buttons.contains(b) {
+                    let trigger_ranges = calibration.and_then(|c| c.triggers);
+                    let scale_trigger = |t: u8, b, side: usize| {
+                        if gbuttons.contains(b) {
                             u8::MAX
+                        } else if raw {
+                            t
+                        } else if let Some((min, max)) = trigger_ranges.map(|ranges| ranges[side]) {
+                            let t = (f64::from(t) - f64::from(min)) / f64::from(max - min);
+                            ((t - CALIBRATED_TRIGGER_DEADZONE) / (1.0 - CALIBRATED_TRIGGER_DEADZONE)
+                                * f64::from(u8::MAX)) as u8
                         } else {
                             ((f64::from(t) - (f64::from(u8::MAX) * TRIGGER_DEADZONE)) * (1.0 - TRIGGER_DEADZONE)) as u8
                         }
                     };
 
-                    let report = UsbReport {
+                    let mut report = UsbReport {
                         buttons: buttons.bits(),
-                        left_trigger: scale_trigger(pad.trigger_left, GButton::L),
-                        right_trigger: scale_trigger(pad.trigger_right, GButton::R),
+                        left_trigger: scale_trigger(pad.trigger_left, GButton::L, 0),
+                        right_trigger: scale_trigger(pad.trigger_right, GButton::R, 1),
                         left_x,
                         left_y,
                         right_x,
                         right_y,
                     };
-                    if let Err(e) = target.update(&report) {
-                        log!(self.logger, "Failed to update target: {}", e);
+
+                    let (combined_triggers, combined_center) = {
+                        let config = self.config.lock();
+                        (config.combined_triggers, config.combined_triggers_center)
+                    };
+                    if let Some(axis) = combined_triggers {
+                        *axis_mut(&mut report, axis) =
+                            combine_triggers(report.left_trigger, report.right_trigger, combined_center);
+                        report.left_trigger = 0;
+                        report.right_trigger = 0;
                     }
+                    *output = Some(PadOutput { gc_buttons: gbuttons, report });
                 }
             }
+            drop(requests);
+
+            let (
+                buttons,
+                slots,
+                press_to_join,
+                always_connected,
+                ports,
+                rumble_period,
+                rumble_max_time,
+                identify_on_connect,
+            ) = {
+                let config = self.config.lock();
+                (
+                    config.buttons,
+                    config.slots.clone(),
+                    config.press_to_join,
+                    usize::from(config.always_connected),
+                    config.ports.clone(),
+                    Duration::from_millis(config.rumble_period.into()),
+                    Duration::from_millis(config.rumble_max_time.into()),
+                    config.identify_on_connect,
+                )
+            };
+            if press_to_join {
+                for (port, output) in outputs.iter_mut().enumerate() {
+                    match output {
+                        None => joined[port] = None,
+                        Some(out) if joined[port].is_none() && out.gc_buttons.contains(GButton::A) => {
+                            if let Some(slot) = (0..4).find(|slot| !joined.contains(&Some(*slot))) {
+                                joined[port] = Some(slot);
+                                join_held[port] = true;
+                                log!(self.logger, "P{} joined as virtual controller {}.", port + 1, slot + 1);
+                            }
+                        },
+                        _ => (),
+                    }
+                    match output {
+                        Some(out) if join_held[port] && out.gc_buttons.contains(GButton::A) => {
+                            out.gc_buttons.remove(GButton::A);
+                            out.report.buttons = map_buttons(&buttons, out.gc_buttons).bits();
+                        },
+                        _ => join_held[port] = false,
+                    }
+                }
+            } else {
+                joined = [None; 4];
+                join_held = [false; 4];
+            }
+            let routes: [Vec<usize>; 4] = std::array::from_fn(|slot| {
+                if press_to_join {
+                    (0..4).filter(|&port| joined[port] == Some(slot)).collect()
+                } else {
+                    slots[slot].ports.clone()
+                }
+            });
+
+            let sources: [Vec<UsbReport>; 4] = std::array::from_fn(|s| {
+                routes[s]
+                    .iter()
+                    .filter_map(|&port| outputs[port])
+                    .map(|PadOutput { gc_buttons, mut report }| {
+                        if let Some(mapping) = &slots[s].buttons {
+                            report.buttons = map_buttons(mapping, gc_buttons).bits();
+                        }
+                        report
+                    })
+                    .collect()
+            });
+
+            // feed each virtual controller from the ports routed to it
+            for (s, slot, sources, target_opt, notif, player) in
+                itertools::izip!(0.., &slots, &sources, targets.iter_mut(), &mut notif_handles, &mut player_numbers)
+            {
+                let wanted = !sources.is_empty() || s < always_connected;
+                match (wanted, target_opt.as_mut()) {
+                    (true, None) => {
+                        let mut target = Target::new();
+                        if let Err(e) = self.vigem.add_target(&mut target) {
+                            log!(self.logger, "Could not add target: {}", e);
+                            continue;
+                        }
+
+                        generations[s] = generations[s].wrapping_add(1);
+                        let generation = generations[s];
+                        let rumble_sender = rumble_sender.clone();
+                        *notif =
+                            match target.register_notification(move |_: &Target, large_motor: u8, small_motor: u8| {
+                                // the receiver only goes away when the daemon stops
+                                let _ = rumble_sender.send((s, generation, (large_motor, small_motor)));
+                            }) {
+                                Ok(handle) => Some(handle),
+                                Err(e) => {
+                                    log!(self.logger, "Could not register rumble notification: {}", e);
+                                    None
+                                },
+                            };
+                        *player = match target.user_index() {
+                            Ok(index) => {
+                                log!(self.logger, "Virtual controller {} connected as player {}.", s + 1, index + 1);
+                                Some(index)
+                            },
+                            Err(e) => {
+                                log!(
+                                    self.logger,
+                                    "Virtual controller {} connected, but its player number is unknown: {}",
+                                    s + 1,
+                                    e
+                                );
+                                None
+                            },
+                        };
+                        *target_opt = Some(target);
+                    },
+                    (false, Some(target)) => {
+                        if let Err(e) = self.vigem.remove_target(target) {
+                            log!(self.logger, "Failed to remove target: {}", e);
+                        }
+                        *target_opt = None;
+                        *notif = None;
+                        rumbles[s] = (0, 0);
+                        *player = None;
+                        log!(self.logger, "Virtual controller {} disconnected.", s + 1);
+                    },
+                    _ => (),
+                }
+                if let Some(target) = target_opt.as_ref()
+                    && let Err(e) = target.update(&merge_reports(sources, slot.stick_merge))
+                {
+                    log!(self.logger, "Failed to update target: {}", e);
+                }
+            }
+
+            // each port shows the player number of the first virtual controller it feeds
+            let port_players: [Option<u8>; 4] = std::array::from_fn(|port| {
+                routes.iter().zip(player_numbers).find(|(route, _)| route.contains(&port)).and_then(|(_, n)| n)
+            });
+            let mut requests = self.requests.lock();
+            if requests.iter().map(|request| request.player).ne(port_players) {
+                for (state, request, player) in itertools::izip!(&mut port_states, requests.iter_mut(), port_players) {
+                    if identify_on_connect
+                        && request.player.is_none()
+                        && let Some(player) = player
+                    {
+                        state.pulses = Some(Pulses::new(player + 1, now));
+                    }
+                    request.player = player;
+                }
+                self.join_sender.notice();
+            }
+
+            // route each virtual controller's rumble back to the ports feeding it,
+            // with pulses overriding the game's rumble while they last
+            for (s, generation, motors) in rumble_receiver.try_iter() {
+                if generation == generations[s] && targets[s].is_some() {
+                    rumbles[s] = motors;
+                }
+            }
+            let mut rumble = [0; 4];
+            for (i, r, state, port, pad, request) in
+                itertools::izip!(0.., &mut rumble, &mut port_states, &ports, &pads, requests.iter_mut())
+            {
+                // identification waits a little for a controller to be there to feel it, since the adapter may not
+                // report it straight away
+                if request.identify != 0 {
+                    if !port.enabled {
+                        log!(self.logger, "Not identifying port {}, the port is disabled.", i + 1);
+                        request.identify = 0;
+                    } else if pad.is_some() {
+                        state.pulses = Some(Pulses::new(request.identify, now));
+                        request.identify = 0;
+                    } else if !fake_frame && now - *state.identify_since.get_or_insert(now) >= IDENTIFY_WAIT {
+                        log!(self.logger, "There's no controller in port {} to identify.", i + 1);
+                        request.identify = 0;
+                    }
+                    if request.identify == 0 {
+                        state.identify_since = None;
+                    }
+                }
+                let intensity = routes
+                    .iter()
+                    .zip(rumbles)
+                    .filter(|(route, _)| route.contains(&i))
+                    .map(|(_, motors)| rumble::intensity(port, motors))
+                    .fold(0.0, f64::max);
+                let intensity = state.rumble_timeout.apply(intensity, rumble_max_time, now);
+                *r = state.rumble.update(intensity, rumble_period, now).into();
+                if let Some(pulses) = &state.pulses {
+                    match pulses.update(now) {
+                        Some(on) => *r = on.into(),
+                        None => state.pulses = None,
+                    }
+                }
+                if !port.enabled || pad.is_none() {
+                    *r = 0;
+                }
+            }
+            drop(requests);
+            if rumble != sent_rumble {
+                self.waiter.send_rumble(rumble);
+                sent_rumble = rumble;
+            }
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        // runs on exit and if the daemon panics, so no motor is left running
+        self.waiter.send_rumble([0; 4]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(buttons: u16, left: (i16, i16), right: (i16, i16), triggers: (u8, u8)) -> UsbReport {
+        UsbReport {
+            buttons,
+            left_trigger: triggers.0,
+            right_trigger: triggers.1,
+            left_x: left.0,
+            left_y: left.1,
+            right_x: right.0,
+            right_y: right.1,
         }
     }
+
+    /// Buttons, left stick, right stick and triggers, in the order `report` takes them.
+    type Parts = (u16, (i16, i16), (i16, i16), (u8, u8));
+
+    fn parts(report: UsbReport) -> Parts {
+        (
+            report.buttons,
+            (report.left_x, report.left_y),
+            (report.right_x, report.right_y),
+            (report.left_trigger, report.right_trigger),
+        )
+    }
+
+    #[test]
+    fn merged_buttons_are_combined() {
+        let reports = [report(0b0101, (0, 0), (0, 0), (0, 0)), report(0b0011, (0, 0), (0, 0), (0, 0))];
+        for merge in [StickMerge::Largest, StickMerge::Primary] {
+            assert_eq!(merge_reports(&reports, merge).buttons, 0b0111);
+        }
+        assert_eq!(parts(merge_reports(&[], StickMerge::Largest)), (0, (0, 0), (0, 0), (0, 0)));
+    }
+
+    #[test]
+    fn largest_merge_picks_each_input_separately() {
+        let reports = [report(0, (1000, -20000), (500, 0), (200, 10)), report(0, (15000, 0), (-3000, 3000), (100, 50))];
+        let merged = merge_reports(&reports, StickMerge::Largest);
+        assert_eq!(parts(merged), (0, (1000, -20000), (-3000, 3000), (200, 50)));
+    }
+
+    #[test]
+    fn primary_merge_falls_back_while_at_rest() {
+        let reports = [report(0, (0, 0), (500, 0), (0, 10)), report(0, (15000, 0), (-3000, 3000), (100, 50))];
+        let merged = merge_reports(&reports, StickMerge::Primary);
+        assert_eq!(parts(merged), (0, (15000, 0), (500, 0), (100, 10)));
+        let reports = [report(0, (10, 0), (0, 0), (0, 0)), report(0, (15000, 0), (0, 0), (0, 0))];
+        assert_eq!(parts(merge_reports(&reports, StickMerge::Primary)).1, (10, 0));
+    }
+
+    #[test]
+    fn combined_triggers() {
+        assert_eq!(combine_triggers(0, 0, 0), 0);
+        assert_eq!(combine_triggers(0, 255, 0), i16::MAX);
+        assert_eq!(combine_triggers(255, 0, 0), -i16::MAX);
+        assert_eq!(combine_triggers(255, 255, 0), 0);
+        // resting at the bottom of the axis, like a single trigger
+        assert_eq!(combine_triggers(0, 0, -100), -i16::MAX);
+        assert_eq!(combine_triggers(0, 255, -100), i16::MAX);
+        assert_eq!(combine_triggers(255, 0, -100), -i16::MAX);
+    }
 }
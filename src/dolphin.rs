@@ -0,0 +1,253 @@
+// Dolphin keeps its GC pad settings in Config/GCPadNew.ini, with a GCPad1 to GCPad4 section for each port, and in
+// profiles under Config/Profiles/GCPad, which have a single Profile section. Its sticks are calibrated at the same
+// 32 angles as GC-X's, but radii, centers and dead zones are stored in % of the full stick range.
+
+use crate::{
+    config::{CALIBRATION_SAMPLES, Calibration, Config},
+    stick,
+};
+use itertools::Itertools;
+use std::{f64::consts::TAU, path::Path};
+
+const PROFILE_SECTION: &str = "Profile";
+const STICK_GROUPS: [&str; 2] = ["Main Stick", "C-Stick"];
+const DEAD_ZONE: &str = "Dead Zone";
+const CALIBRATION: &str = "Calibration";
+const CENTER: &str = "Center";
+// Size of the octagon gate Dolphin uses when a stick isn't calibrated
+const GATE_SIZE: &str = "Gate Size";
+
+/// Settings taken from a Dolphin GC pad.
+pub struct DolphinPad {
+    /// The main stick's dead zone, in %. Dolphin's dead zone is measured from the calibrated center, GC-X's from
+    /// each axis's center, so they only match closely for small values. It isn't applied, since GC-X's dead zone is
+    /// shared by every port.
+    pub deadzone: Option<u8>,
+    /// Dolphin doesn't calibrate triggers, so `triggers` is always `None`.
+    pub calibration: Calibration,
+}
+
+impl DolphinPad {
+    /// Use the imported calibration for a port, keeping its trigger calibration.
+    pub fn apply(self, config: &mut Config, port: usize) {
+        let port = &mut config.ports[port];
+        let triggers = port.calibration.as_ref().and_then(|calibration| calibration.triggers);
+        port.calibration = Some(Calibration { triggers, ..self.calibration });
+    }
+}
+
+// Dolphin's controller expressions can contain quotes and backslashes, which must be kept as they are
+const PARSE_OPTION: ini::ParseOption = ini::ParseOption {
+    enabled_quote: false,
+    enabled_escape: false,
+    enabled_indented_mutiline_value: false,
+    enabled_preserve_key_leading_whitespace: false,
+};
+
+/// The section for a port: the whole file if it's a profile, otherwise that port's pad.
+/// New or empty files are profiles if they're anywhere in a Profiles folder.
+fn section_name(ini: &ini::Ini, path: &Path, port: usize) -> String {
+    let is_new = ini.sections().all(|name| name.is_none());
+    let in_profiles = path.parent().is_some_and(|dir| dir.iter().any(|name| name.eq_ignore_ascii_case("Profiles")));
+    if ini.section(Some(PROFILE_SECTION)).is_some() || (is_new && in_profiles) {
+        PROFILE_SECTION.into()
+    } else {
+        format!("GCPad{}", port + 1)
+    }
+}
+
+fn key(group: &str, name: &str) -> String {
+    format!("{}/{}", group, name)
+}
+
+fn percent_to_raw(percent: f64) -> i16 {
+    (percent / 100.0 * f64::from(i16::MAX)).round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+}
+
+fn raw_to_percent(raw: i16) -> f64 {
+    f64::from(raw) / f64::from(i16::MAX) * 100.0
+}
+
+fn format_numbers(numbers: impl IntoIterator<Item = f64>) -> String {
+    numbers.into_iter().map(|n| format!("{:.2}", n)).join(" ")
+}
+
+/// Read the dead zone and stick calibration of a port's pad, or of a profile.
+pub fn import(path: &Path, port: usize) -> Result<DolphinPad, String> {
+    let ini = ini::Ini::load_from_file_opt(path, PARSE_OPTION).map_err(|e| e.to_string())?;
+    let section_name = section_name(&ini, path, port);
+    let section =
+        ini.section(Some(section_name.as_str())).ok_or_else(|| format!("there's no {} section", section_name))?;
+    let numbers = |key: &str, count: usize| -> Result<Option<Vec<f64>>, String> {
+        let Some(text) = section.get(key) else {
+            return Ok(None);
+        };
+        match text.split_whitespace().map(str::parse).collect::<Result<Vec<f64>, _>>() {
+            Ok(numbers) if numbers.len() == count => Ok(Some(numbers)),
+            _ => Err(format!("{} should be {} numbers, but it's \"{}\"", key, count, text)),
+        }
+    };
+    let mut calibration = Calibration::default();
+    for (stick, group) in calibration.sticks_mut().into_iter().zip(STICK_GROUPS) {
+        if let Some(radii) = numbers(&key(group, CALIBRATION), CALIBRATION_SAMPLES)? {
+            stick.gate = radii.iter().map(|r| r / 100.0).collect();
+        } else if let Some(size) = numbers(&key(group, GATE_SIZE), 1)? {
+            stick.gate = (0..CALIBRATION_SAMPLES)
+                .map(|i| stick::octagon_radius_at_angle(TAU * i as f64 / CALIBRATION_SAMPLES as f64) * size[0] / 100.0)
+                .collect();
+        }
+        if !stick::valid_gate(&stick.gate) {
+            return Err(format!(
+                "the {} gate goes outside {}% to {}%",
+                group,
+                stick::MIN_GATE_RADIUS * 100.0,
+                stick::MAX_GATE_RADIUS * 100.0
+            ));
+        }
+        if let Some(center) = numbers(&key(group, CENTER), 2)? {
+            stick.center = (percent_to_raw(center[0]), percent_to_raw(center[1]));
+        }
+    }
+    let deadzone = numbers(&key(STICK_GROUPS[0], DEAD_ZONE), 1)?.map(|dz| dz[0].round().clamp(0.0, 100.0) as u8);
+    Ok(DolphinPad { deadzone, calibration })
+}
+
+/// Write the dead zone and a port's stick calibration into a Dolphin pad or profile, keeping its other settings.
+pub fn export(path: &Path, port: usize, config: &Config) -> Result<(), String> {
+    let mut ini = if path.exists() {
+        ini::Ini::load_from_file_opt(path, PARSE_OPTION).map_err(|e| e.to_string())?
+    } else {
+        ini::Ini::new()
+    };
+    let section_name = section_name(&ini, path, port);
+    let mut section = ini.with_section(Some(section_name));
+    let calibration = config.ports[port].calibration.clone().unwrap_or_default();
+    for (stick, group) in calibration.sticks().into_iter().zip(STICK_GROUPS) {
+        section.set(key(group, DEAD_ZONE), format_numbers([f64::from(config.deadzone)]));
+        // without these, Dolphin uses its defaults for an official controller, the same as GC-X
+        if stick.gate.is_empty() {
+            section.delete(&key(group, CALIBRATION));
+        } else {
+            section.set(key(group, CALIBRATION), format_numbers(stick.gate.iter().map(|r| r * 100.0)));
+        }
+        if stick.center == (0, 0) {
+            section.delete(&key(group, CENTER));
+        } else {
+            section.set(
+                key(group, CENTER),
+                format_numbers([raw_to_percent(stick.center.0), raw_to_percent(stick.center.1)]),
+            );
+        }
+    }
+    let write_option =
+        ini::WriteOption { escape_policy: ini::EscapePolicy::Nothing, kv_separator: " = ", ..Default::default() };
+    ini.write_to_file_opt(path, write_option).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::TestDir;
+
+    #[test]
+    fn percent_conversions() {
+        assert_eq!(percent_to_raw(100.0), i16::MAX);
+        assert_eq!(percent_to_raw(-100.0), -i16::MAX);
+        assert_eq!(percent_to_raw(-200.0), i16::MIN);
+        assert_eq!(percent_to_raw(raw_to_percent(1234)), 1234);
+        assert_eq!(format_numbers([1.0, 0.125, -3.5]), "1.00 0.12 -3.50");
+    }
+
+    #[test]
+    fn imports_pad() {
+        let dir = TestDir::new("dolphin-import");
+        let path = dir.join("GCPadNew.ini");
+        let radii = format_numbers((0..CALIBRATION_SAMPLES).map(|i| 70.0 + i as f64));
+        std::fs::write(
+            &path,
+            format!(
+                "[GCPad1]\nMain Stick/Dead Zone = 15.00\n\
+                [GCPad2]\nButtons/A = `Button A`\nMain Stick/Calibration = {}\nMain Stick/Center = 1.00 -2.00\n\
+                C-Stick/Gate Size = 80.00\n",
+                radii
+            ),
+        )
+        .unwrap();
+        let pad = import(&path, 1).unwrap();
+        assert_eq!(pad.deadzone, None);
+        let main_stick = &pad.calibration.main_stick;
+        assert_eq!(main_stick.gate.len(), CALIBRATION_SAMPLES);
+        assert!((main_stick.gate[3] - 0.73).abs() < 1e-9);
+        assert_eq!(main_stick.center, (percent_to_raw(1.0), percent_to_raw(-2.0)));
+        // an uncalibrated stick gets Dolphin's octagon
+        let c_stick = &pad.calibration.c_stick;
+        assert!((c_stick.gate[0] - 0.8).abs() < 1e-9);
+        assert!(c_stick.gate[2] < c_stick.gate[0]);
+        assert_eq!(c_stick.center, (0, 0));
+        assert_eq!(import(&path, 0).unwrap().deadzone, Some(15));
+    }
+
+    #[test]
+    fn rejects_bad_pads() {
+        let dir = TestDir::new("dolphin-reject");
+        let path = dir.join("GCPadNew.ini");
+        std::fs::write(&path, "[GCPad1]\nC-Stick/Gate Size = 5.00\n").unwrap();
+        assert!(import(&path, 0).is_err());
+        std::fs::write(&path, "[GCPad1]\nMain Stick/Calibration = 1 2 3\n").unwrap();
+        assert!(import(&path, 0).is_err());
+        assert!(import(&path, 1).is_err());
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let dir = TestDir::new("dolphin-round-trip");
+        let path = dir.join("GCPadNew.ini");
+        std::fs::write(&path, "[GCPad3]\nButtons/A = `Button A`\n").unwrap();
+        let mut config = Config::default();
+        config.deadzone = 12;
+        let mut calibration = Calibration::default();
+        calibration.main_stick.gate = (0..CALIBRATION_SAMPLES).map(|i| 0.7 + i as f64 / 1000.0).collect();
+        calibration.c_stick.center = (300, -300);
+        config.ports[2].calibration = Some(calibration);
+        export(&path, 2, &config).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("Buttons/A = `Button A`"));
+        let pad = import(&path, 2).unwrap();
+        assert_eq!(pad.deadzone, Some(12));
+        let calibration = config.ports[2].calibration.as_ref().unwrap();
+        for (imported, exported) in pad.calibration.sticks().into_iter().zip(calibration.sticks()) {
+            assert_eq!(imported.gate.len(), exported.gate.len());
+            assert!(imported.gate.iter().zip(&exported.gate).all(|(a, b)| (a - b).abs() < 1e-4));
+            assert!(imported.center.0.abs_diff(exported.center.0) <= 2);
+            assert!(imported.center.1.abs_diff(exported.center.1) <= 2);
+        }
+    }
+
+    #[test]
+    fn exports_new_profiles() {
+        let root = TestDir::new("dolphin-profiles");
+        let dir = root.join("Profiles").join("GCPad");
+        std::fs::create_dir_all(&dir).unwrap();
+        export(&dir.join("new.ini"), 1, &Config::default()).unwrap();
+        assert!(std::fs::read_to_string(dir.join("new.ini")).unwrap().contains("[Profile]"));
+        let other = TestDir::new("dolphin-not-profiles");
+        let path = other.join("pad.ini");
+        export(&path, 1, &Config::default()).unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().contains("[GCPad2]"));
+    }
+
+    #[test]
+    fn apply_keeps_triggers_and_dead_zone() {
+        let mut config = Config::default();
+        config.deadzone = 7;
+        let triggers = Some([(10, 200), (20, 210)]);
+        config.ports[0].calibration = Some(Calibration { triggers, ..Default::default() });
+        let mut calibration = Calibration::default();
+        calibration.main_stick.center = (5, 5);
+        DolphinPad { deadzone: Some(30), calibration }.apply(&mut config, 0);
+        assert_eq!(config.deadzone, 7);
+        let calibration = config.ports[0].calibration.as_ref().unwrap();
+        assert_eq!(calibration.main_stick.center, (5, 5));
+        assert_eq!(calibration.triggers, triggers);
+    }
+}
@@ -1,11 +1,20 @@
 #![windows_subsystem = "windows"]
 
 use parking_lot::{Condvar, Mutex, Once};
-use std::sync::Arc;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 mod adapter;
+mod assist;
+mod calibration;
 mod config;
 mod daemon;
+mod dolphin;
+mod rumble;
+mod stick;
 mod ui;
 
 #[macro_export]
@@ -18,7 +27,82 @@ macro_rules! log {
     }}
 }
 
+// How long to wait for the daemon to shut down on exit
+const EXIT_TIMEOUT: Duration = Duration::from_millis(500);
+// How often to check the settings file for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// Most rumble pulses --identify can ask for
+const MAX_IDENTIFY_COUNT: u8 = 10;
+
+/// Options given on the command line.
+#[derive(Default)]
+struct Args {
+    /// Ports to calibrate as soon as a controller is connected to them.
+    calibrate: [bool; 4],
+    /// How many times to pulse each port's rumble once the adapter is found. It's dropped if the port has no
+    /// controller by then. This only applies to the instance being started; a running one can't be reached.
+    identify: [u8; 4],
+    /// Settings file to use instead of the usual one.
+    config: Option<PathBuf>,
+    /// Don't start with settings that have any problems in them, or reload settings that do.
+    strict: bool,
+    /// Problems with the arguments, to be logged once the UI is up.
+    errors: Vec<String>,
+}
+
+fn parse_args() -> Args {
+    let mut parsed = Args::default();
+    let mut args = std::env::args_os().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--calibrate") => match args.next().and_then(|port| port.to_str()?.parse::<usize>().ok()) {
+                Some(port @ 1..=4) => parsed.calibrate[port - 1] = true,
+                _ => parsed.errors.push("--calibrate needs a port number from 1 to 4.".into()),
+            },
+            // --identify PORT [COUNT], pulsing as many times as the port number unless told otherwise
+            Some("--identify") => match args.next().and_then(|port| port.to_str()?.parse::<usize>().ok()) {
+                Some(port @ 1..=4) => {
+                    let count = args.next_if(|count| count.to_str().is_some_and(|count| !count.starts_with("--")));
+                    match count.map(|count| count.to_str().and_then(|count| count.parse::<u8>().ok())) {
+                        None => parsed.identify[port - 1] = port as u8,
+                        Some(Some(count @ 1..=MAX_IDENTIFY_COUNT)) => parsed.identify[port - 1] = count,
+                        Some(_) => parsed.errors.push(format!(
+                            "--identify's pulse count must be a number from 1 to {}.",
+                            MAX_IDENTIFY_COUNT
+                        )),
+                    }
+                },
+                _ => parsed.errors.push("--identify needs a port number from 1 to 4.".into()),
+            },
+            Some("--strict") => parsed.strict = true,
+            Some("--config") => match args.next() {
+                Some(path) => parsed.config = Some(path.into()),
+                None => parsed.errors.push("--config needs the path of a settings file.".into()),
+            },
+            _ => parsed.errors.push(format!("Unknown command line argument: {}", arg.to_string_lossy())),
+        }
+    }
+    parsed
+}
+
 fn main() {
+    let args = parse_args();
+
+    let config_path = config::config_path(args.config);
+
+    // the UI only loads the settings once it's running, so check them before anything starts
+    if args.strict {
+        let problems = config::Config::load(&config_path, &|_| ()).problems;
+        if !problems.is_empty() {
+            let list: String = problems.iter().map(|problem| format!("\n{}", problem)).collect();
+            ui::show_error(
+                "Problems with the settings",
+                &format!("Strict mode is on, and there are problems with the settings:\n{}", list),
+            );
+            return;
+        }
+    }
+
     let exit_once = Arc::new(Once::new());
 
     let config = Arc::new(Mutex::new(Default::default()));
@@ -27,7 +111,17 @@ fn main() {
 
     let joy_connected = Arc::new(Mutex::new([false; 4]));
 
-    let ui = match ui::init_app(exit_once.clone(), config.clone(), must_center.clone(), joy_connected.clone()) {
+    let requests = Arc::new(Mutex::new(Default::default()));
+
+    let ui = match ui::init_app(
+        exit_once.clone(),
+        config_path.clone(),
+        args.strict,
+        config.clone(),
+        must_center.clone(),
+        joy_connected.clone(),
+        requests.clone(),
+    ) {
         Ok(ui) => ui,
         Err(e) => {
             ui::show_error("Could not initialize UI", &format!("Could not initialize UI: {}", e));
@@ -36,9 +130,17 @@ fn main() {
     };
     let logger = ui.logger.clone();
 
+    for error in &args.errors {
+        log!(logger, "{}", error);
+    }
+    for (request, calibrate, identify) in itertools::izip!(requests.lock().iter_mut(), args.calibrate, args.identify) {
+        request.calibrate = calibrate;
+        request.identify = identify;
+    }
+
     let wait_for_init = Arc::new((Mutex::new(false), Condvar::new()));
 
-    std::thread::Builder::new()
+    let daemon_thread = std::thread::Builder::new()
         .name("daemon".into())
         .spawn({
             let wait_for_init = wait_for_init.clone();
@@ -53,6 +155,7 @@ fn main() {
                     config,
                     must_center,
                     joy_connected,
+                    requests,
                     join_sender,
                     leave_sender,
                     exit_sender,
@@ -83,5 +186,28 @@ fn main() {
         return;
     }
 
+    std::thread::Builder::new()
+        .name("config watcher".into())
+        .spawn({
+            let exit_once = exit_once.clone();
+            let reload_sender = ui.reload_sender;
+            move || {
+                let mut watcher = config::FileWatcher::new(config_path);
+                while !exit_once.state().done() {
+                    std::thread::sleep(CONFIG_POLL_INTERVAL);
+                    if watcher.poll(Instant::now()) {
+                        reload_sender.notice();
+                    }
+                }
+            }
+        })
+        .unwrap();
+
     ui::run_ui();
+
+    // give the daemon a moment to stop any rumble before the process ends
+    let deadline = Instant::now() + EXIT_TIMEOUT;
+    while !daemon_thread.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
 }
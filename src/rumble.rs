@@ -0,0 +1,149 @@
+use crate::config::PortConfig;
+use std::time::{Duration, Instant};
+
+/// How strongly a port should rumble for a virtual controller's motor speeds, from 0 to 1.
+/// The mix is scaled so the motor that counts more gives full rumble on its own.
+pub fn intensity(port: &PortConfig, (large_motor, small_motor): (u8, u8)) -> f64 {
+    let weight = f64::from(port.rumble_large_weight) / 100.0;
+    let mixed = (f64::from(large_motor) * weight + f64::from(small_motor) * (1.0 - weight))
+        / (f64::from(u8::MAX) * weight.max(1.0 - weight));
+    (mixed * f64::from(port.rumble_strength) / 100.0).min(1.0)
+}
+
+/// Approximates a rumble intensity with the adapter's on/off rumble, by keeping the motor on for part of each
+/// period in proportion to the intensity.
+#[derive(Default)]
+pub struct DutyCycle {
+    period_start: Option<Instant>,
+}
+
+impl DutyCycle {
+    /// Whether the motor should be on this frame. A zero period turns the motor on for any intensity.
+    pub fn update(&mut self, intensity: f64, period: Duration, now: Instant) -> bool {
+        if intensity <= 0.0 {
+            self.period_start = None;
+            return false;
+        }
+        if intensity >= 1.0 || period.is_zero() {
+            return true;
+        }
+        let start = self.period_start.get_or_insert(now);
+        if now - *start >= period {
+            *start = now;
+        }
+        (now - *start).as_secs_f64() < intensity * period.as_secs_f64()
+    }
+}
+
+/// Forces the motor off once rumble has been asked for without a break for too long.
+#[derive(Default)]
+pub struct RumbleTimeout {
+    since: Option<Instant>,
+}
+
+impl RumbleTimeout {
+    /// The intensity to use, which is 0 once rumble has gone on for `max_time`, until it stops being asked for.
+    /// A zero `max_time` means there's no limit.
+    pub fn apply(&mut self, intensity: f64, max_time: Duration, now: Instant) -> f64 {
+        if intensity <= 0.0 {
+            self.since = None;
+            return 0.0;
+        }
+        let since = *self.since.get_or_insert(now);
+        if !max_time.is_zero() && now - since >= max_time { 0.0 } else { intensity }
+    }
+}
+
+// Length of each identification or confirmation pulse, and of the gap after it
+const PULSE_TIME: Duration = Duration::from_millis(150);
+
+/// A number of short rumble pulses, to confirm something happened or to tell controllers apart.
+pub struct Pulses {
+    start: Instant,
+    count: u8,
+}
+
+impl Pulses {
+    pub fn new(count: u8, now: Instant) -> Self {
+        Self { start: now, count }
+    }
+
+    /// Whether the motor should be on, or `None` once all the pulses are done.
+    pub fn update(&self, now: Instant) -> Option<bool> {
+        let step = (now - self.start).as_millis() / PULSE_TIME.as_millis();
+        (step + 1 < u128::from(self.count) * 2).then_some(step.is_multiple_of(2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(8);
+    const PERIOD: Duration = Duration::from_millis(80);
+
+    /// How many frames of one period the motor is on for.
+    fn frames_on(intensity: f64) -> usize {
+        let mut duty = DutyCycle::default();
+        let start = Instant::now();
+        (0..10).filter(|&i| duty.update(intensity, PERIOD, start + FRAME * i)).count()
+    }
+
+    #[test]
+    fn duty_cycle_follows_intensity() {
+        assert_eq!(frames_on(0.0), 0);
+        assert_eq!(frames_on(0.3), 3);
+        assert_eq!(frames_on(0.5), 5);
+        assert_eq!(frames_on(1.0), 10);
+    }
+
+    #[test]
+    fn duty_cycle_without_period_is_on() {
+        let mut duty = DutyCycle::default();
+        assert!(duty.update(0.1, Duration::ZERO, Instant::now()));
+    }
+
+    #[test]
+    fn rumble_times_out() {
+        let mut timeout = RumbleTimeout::default();
+        let max_time = Duration::from_secs(1);
+        let now = Instant::now();
+        assert_eq!(timeout.apply(0.5, max_time, now), 0.5);
+        assert_eq!(timeout.apply(0.5, max_time, now + max_time), 0.0);
+        // it stays off until the game stops asking for rumble
+        assert_eq!(timeout.apply(0.5, max_time, now + max_time * 2), 0.0);
+        assert_eq!(timeout.apply(0.0, max_time, now + max_time * 3), 0.0);
+        assert_eq!(timeout.apply(0.5, max_time, now + max_time * 4), 0.5);
+        assert_eq!(timeout.apply(0.5, Duration::ZERO, now + max_time * 10), 0.5);
+    }
+
+    #[test]
+    fn pulses_turn_on_and_off() {
+        let start = Instant::now();
+        let pulses = Pulses::new(3, start);
+        let states: Vec<_> = (0..6).map(|i| pulses.update(start + PULSE_TIME * i + FRAME)).collect();
+        assert_eq!(states, [Some(true), Some(false), Some(true), Some(false), Some(true), None]);
+        assert_eq!(Pulses::new(0, start).update(start), None);
+    }
+
+    #[test]
+    fn either_motor_gives_full_rumble_by_default() {
+        let port = PortConfig::default();
+        assert_eq!(intensity(&port, (255, 0)), 1.0);
+        assert_eq!(intensity(&port, (0, 255)), 1.0);
+        assert!((intensity(&port, (128, 0)) - 128.0 / 255.0).abs() < 1e-9);
+        assert_eq!(intensity(&port, (0, 0)), 0.0);
+    }
+
+    #[test]
+    fn intensity_mixes_motors() {
+        let port = PortConfig { rumble_large_weight: 100, rumble_strength: 50, ..Default::default() };
+        assert!((intensity(&port, (255, 0)) - 0.5).abs() < 1e-9);
+        assert_eq!(intensity(&port, (0, 255)), 0.0);
+        let port = PortConfig { rumble_large_weight: 25, ..Default::default() };
+        assert_eq!(intensity(&port, (0, 255)), 1.0);
+        assert!((intensity(&port, (255, 0)) - 1.0 / 3.0).abs() < 1e-9);
+        let port = PortConfig { rumble_strength: 1000, ..Default::default() };
+        assert_eq!(intensity(&port, (100, 0)), 1.0);
+    }
+}
@@ -0,0 +1,635 @@
+use crate::config::{CALIBRATION_SAMPLES, PortConfig, StickConfig};
+use std::{
+    collections::HashSet,
+    f64::consts::{PI, TAU},
+    time::{Duration, Instant},
+};
+
+// Maths functions and controller constants graciously lifted from Dolphin
+pub fn square_radius_at_angle(angle: f64) -> f64 {
+    let section_angle = TAU / 4.0;
+    1.0 / (((angle + section_angle / 2.0) % section_angle) - section_angle / 2.0).cos()
+}
+
+pub fn octagon_radius_at_angle(angle: f64) -> f64 {
+    let sides = 8.0;
+    let sum_int_angles = (sides - 2.0) * PI;
+    let half_int_angle = sum_int_angles / sides / 2.0;
+
+    let angle = angle % (TAU / sides);
+    1.0 / (PI - angle - half_int_angle).sin() * half_int_angle.sin()
+}
+pub const MAIN_STICK_GATE_RADIUS: f64 = 0.7937125;
+pub const C_STICK_GATE_RADIUS: f64 = 0.7221375;
+
+/// Radius of a calibrated gate at an angle in radians, interpolating between the samples.
+pub fn calibrated_radius_at_angle(gate: &[f64], angle: f64) -> f64 {
+    let pos = angle.rem_euclid(TAU) / TAU * gate.len() as f64;
+    let i = pos as usize % gate.len();
+    let next = (i + 1) % gate.len();
+    gate[i] + (gate[next] - gate[i]) * pos.fract()
+}
+
+// A gate is only usable if it reaches at least this far in every direction, and no further than the maximum.
+pub const MIN_GATE_RADIUS: f64 = 0.3;
+pub const MAX_GATE_RADIUS: f64 = 1.5;
+// A recorded trigger is only usable if it moves at least this far.
+const MIN_TRIGGER_TRAVEL: u8 = 64;
+
+/// Records how far a stick reaches in every direction.
+pub struct GateRecorder {
+    samples: [f64; CALIBRATION_SAMPLES],
+}
+
+impl Default for GateRecorder {
+    fn default() -> Self {
+        Self { samples: [0.0; CALIBRATION_SAMPLES] }
+    }
+}
+
+impl GateRecorder {
+    /// Record a centered stick position.
+    pub fn record(&mut self, (x, y): (i16, i16)) {
+        let (fx, fy) = (f64::from(x), f64::from(y));
+        let pos = fy.atan2(fx).rem_euclid(TAU) / TAU * CALIBRATION_SAMPLES as f64;
+        let sample = &mut self.samples[pos.round() as usize % CALIBRATION_SAMPLES];
+        *sample = sample.max(fx.hypot(fy) / f64::from(i16::MAX));
+    }
+
+    /// The recorded gate, if the stick has been moved all the way around it.
+    pub fn finish(&self) -> Option<Vec<f64>> {
+        valid_gate(&self.samples).then(|| self.samples.to_vec())
+    }
+}
+
+/// Whether every radius of a gate is in the usable range.
+pub fn valid_gate(gate: &[f64]) -> bool {
+    gate.iter().all(|r| (MIN_GATE_RADIUS..=MAX_GATE_RADIUS).contains(r))
+}
+
+/// Records the lowest and highest values a trigger has reached.
+pub struct TriggerRecorder {
+    min: u8,
+    max: u8,
+}
+
+impl Default for TriggerRecorder {
+    fn default() -> Self {
+        Self { min: u8::MAX, max: u8::MIN }
+    }
+}
+
+impl TriggerRecorder {
+    pub fn record(&mut self, value: u8) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The recorded range, if the trigger has been pressed far enough.
+    pub fn finish(&self) -> Option<(u8, u8)> {
+        (self.max.saturating_sub(self.min) >= MIN_TRIGGER_TRAVEL).then_some((self.min, self.max))
+    }
+}
+
+/// Distance between two angles in degrees, going whichever way round is shorter.
+fn angle_distance(a: f64, b: f64) -> f64 {
+    ((a - b + 180.0).rem_euclid(360.0) - 180.0).abs()
+}
+
+/// If the stick is within the snap window of a notch, move it to that notch's target angle.
+/// The distance from the center is preserved.
+pub fn snap_angle((x, y): (i16, i16), stick: &StickConfig) -> (i16, i16) {
+    if stick.snap_window == 0 || (x, y) == (0, 0) {
+        return (x, y);
+    }
+    let (fx, fy) = (f64::from(x), f64::from(y));
+    let angle = fy.atan2(fx).to_degrees();
+    let targets = stick.snap_angles.iter().map(|&a| f64::from(a));
+    let nearest = if stick.notches.len() == stick.snap_angles.len() {
+        stick
+            .notches
+            .iter()
+            .copied()
+            .zip(targets)
+            .min_by(|a, b| angle_distance(angle, a.0).total_cmp(&angle_distance(angle, b.0)))
+    } else {
+        targets.map(|a| (a, a)).min_by(|a, b| angle_distance(angle, a.0).total_cmp(&angle_distance(angle, b.0)))
+    };
+    match nearest {
+        Some((notch, target)) if angle_distance(angle, notch) <= f64::from(stick.snap_window) => {
+            let magnitude = fx.hypot(fy);
+            let target = target.to_radians();
+            ((magnitude * target.cos()) as i16, (magnitude * target.sin()) as i16)
+        },
+        _ => (x, y),
+    }
+}
+
+// Only positions this far out are considered to be in a notch.
+const NOTCH_MIN_RADIUS: f64 = 0.5;
+// How far from its target angle a notch is looked for, in degrees.
+const NOTCH_SEARCH_WINDOW: i32 = 15;
+
+/// Records where a stick rests while the user pushes it into each of its notches.
+pub struct NotchLearner {
+    // how many frames the stick spent at each whole degree
+    histogram: [u32; 360],
+}
+
+impl Default for NotchLearner {
+    fn default() -> Self {
+        Self { histogram: [0; 360] }
+    }
+}
+
+impl NotchLearner {
+    /// Record a centered stick position.
+    pub fn record(&mut self, (x, y): (i16, i16)) {
+        let (fx, fy) = (f64::from(x), f64::from(y));
+        if fx.hypot(fy) >= f64::from(i16::MAX) * NOTCH_MIN_RADIUS {
+            let angle = fy.atan2(fx).to_degrees().rem_euclid(360.0);
+            self.histogram[angle as usize % 360] += 1;
+        }
+    }
+
+    /// Find the notch for each target angle: the most visited degree near it, refined to the average of it
+    /// and its neighbours. Targets with no recorded positions nearby are kept as they are.
+    pub fn finish(&self, targets: &[u16]) -> Vec<f64> {
+        let count = |deg: i32| self.histogram[deg.rem_euclid(360) as usize];
+        targets
+            .iter()
+            .map(|&target| {
+                let target = i32::from(target);
+                let peak = (target - NOTCH_SEARCH_WINDOW..=target + NOTCH_SEARCH_WINDOW)
+                    .max_by_key(|&deg| count(deg))
+                    .unwrap_or(target);
+                let weight: u32 = (peak - 1..=peak + 1).map(count).sum();
+                if weight == 0 {
+                    f64::from(target)
+                } else {
+                    let sum: f64 = (peak - 1..=peak + 1).map(|deg| f64::from(deg) * f64::from(count(deg))).sum();
+                    (sum / f64::from(weight) + 0.5).rem_euclid(360.0)
+                }
+            })
+            .collect()
+    }
+}
+
+// A tilt past this is considered a hard tilt that could snap back when released.
+const SNAPBACK_PEAK: i16 = i16::MAX / 2;
+// Snapback rarely overshoots this far, so anything past it is real input.
+const SNAPBACK_OVERSHOOT: i16 = i16::MAX / 2;
+// If the last output was further out than this, hold the center instead.
+const SNAPBACK_HOLD_LIMIT: i16 = i16::MAX / 4;
+
+#[derive(Default)]
+struct SnapbackAxis {
+    /// Time and direction of the last hard tilt.
+    peak: Option<(Instant, i16)>,
+    last: i16,
+}
+
+impl SnapbackAxis {
+    fn apply(&mut self, value: i16, now: Instant, window: Duration) -> i16 {
+        if value.abs() > SNAPBACK_PEAK {
+            self.peak = Some((now, value.signum()));
+        }
+        let out = match self.peak {
+            Some((time, sign)) if now - time < window => {
+                if value.signum() == -sign && value.abs() < SNAPBACK_OVERSHOOT {
+                    // crossed the center right after a hard tilt, so hold the last value from before the crossing
+                    if self.last.signum() != -sign && self.last.abs() < SNAPBACK_HOLD_LIMIT { self.last } else { 0 }
+                } else {
+                    value
+                }
+            },
+            _ => {
+                self.peak = None;
+                value
+            },
+        };
+        self.last = out;
+        out
+    }
+}
+
+// Only positions this far out have a direction for the tremor filter, in fractions of the stick range.
+const TREMOR_MIN_RADIUS: f64 = 0.2;
+// Directions within this many degrees of each other count as the same for the tremor filter.
+const TREMOR_ANGLE_TOLERANCE: f64 = 30.0;
+
+/// Running state of the filters for one stick.
+#[derive(Default)]
+pub struct StickFilterState {
+    /// When the stick started pointing in its current direction, and that direction in degrees.
+    /// The direction is `None` while the stick is near the center.
+    tremor: Option<(Instant, Option<f64>)>,
+    tremor_output: (i16, i16),
+    snapback: [SnapbackAxis; 2],
+    smoothed: Option<(f64, f64)>,
+    output: (i16, i16),
+    last_time: Option<Instant>,
+}
+
+impl StickFilterState {
+    /// Filter a centered stick position. `now` should be the timestamp of the current frame.
+    pub fn apply(&mut self, filter: &StickConfig, (x, y): (i16, i16), now: Instant) -> (i16, i16) {
+        let dt = self.last_time.map(|t| now - t).unwrap_or_default();
+        self.last_time = Some(now);
+
+        let (mut x, mut y) = (x, y);
+        if filter.tremor_time != 0 {
+            let (fx, fy) = (f64::from(x), f64::from(y));
+            let direction =
+                (fx.hypot(fy) >= f64::from(i16::MAX) * TREMOR_MIN_RADIUS).then(|| fy.atan2(fx).to_degrees());
+            let same_direction = |old: Option<f64>| match (old, direction) {
+                (Some(old), Some(new)) => angle_distance(old, new) <= TREMOR_ANGLE_TOLERANCE,
+                (old, new) => old.is_none() && new.is_none(),
+            };
+            let since = match self.tremor {
+                Some((since, old)) if same_direction(old) => since,
+                _ => {
+                    self.tremor = Some((now, direction));
+                    now
+                },
+            };
+            if now - since >= Duration::from_millis(filter.tremor_time.into()) {
+                self.tremor_output = (x, y);
+            }
+            (x, y) = self.tremor_output;
+        } else {
+            self.tremor = None;
+        }
+
+        if filter.snapback_time != 0 {
+            let window = Duration::from_millis(filter.snapback_time.into());
+            x = self.snapback[0].apply(x, now, window);
+            y = self.snapback[1].apply(y, now, window);
+        }
+
+        let (mut fx, mut fy) = (f64::from(x), f64::from(y));
+        if filter.smoothing_time != 0 {
+            if let Some((sx, sy)) = self.smoothed {
+                let factor = 1.0 - (-dt.as_secs_f64() * 1000.0 / f64::from(filter.smoothing_time)).exp();
+                fx = sx + (fx - sx) * factor;
+                fy = sy + (fy - sy) * factor;
+            }
+            self.smoothed = Some((fx, fy));
+        } else {
+            self.smoothed = None;
+        }
+
+        let threshold = f64::from(i16::MAX) * f64::from(filter.hysteresis) / 100.0;
+        let (ox, oy) = (f64::from(self.output.0), f64::from(self.output.1));
+        // a stick back at its center always gets there, even if it's closer than the threshold to the last output
+        if (fx - ox).hypot(fy - oy) >= threshold || (fx as i16, fy as i16) == (0, 0) {
+            self.output = (fx as i16, fy as i16);
+        }
+        self.output
+    }
+}
+
+// A resting stick may wobble this far, in fractions of the stick range, without counting as moved.
+const DRIFT_IDLE_TOLERANCE: f64 = 0.02;
+// Time constant with which the center follows a resting stick, in seconds.
+const DRIFT_FOLLOW_TIME: f64 = 1.0;
+// The center only follows a resting stick this close to it, in fractions of the stick range, so a gently held tilt
+// is never taken for drift. Drift builds up slowly, so the center keeps up with it in small steps.
+const DRIFT_MAX_STEP: f64 = 0.015;
+
+/// Tracks a stick's resting position to compensate for drift.
+#[derive(Default)]
+pub struct DriftState {
+    /// The center as it was last set from outside, which compensation can't move too far from.
+    base: (i16, i16),
+    /// The compensated center, more precisely than it's stored.
+    center: (f64, f64),
+    /// When the stick started resting, and where.
+    idle: Option<(Instant, (i16, i16))>,
+    last_time: Option<Instant>,
+}
+
+impl DriftState {
+    /// Move `center` towards the uncentered stick position `raw` if the stick has been resting near it.
+    pub fn apply(&mut self, port: &PortConfig, raw: (i16, i16), center: &mut (i16, i16), now: Instant) {
+        let dt = self.last_time.map(|t| now - t).unwrap_or_default();
+        self.last_time = Some(now);
+
+        if (self.center.0.round() as i16, self.center.1.round() as i16) != *center {
+            // the center was set elsewhere, so start over from there
+            self.base = *center;
+            self.center = (f64::from(center.0), f64::from(center.1));
+            self.idle = None;
+        }
+        if !port.drift_compensation {
+            return;
+        }
+
+        let range = f64::from(i16::MAX);
+        let (x, y) = (f64::from(raw.0), f64::from(raw.1));
+        if (x - self.center.0).hypot(y - self.center.1) > range * f64::from(port.drift_window) / 100.0 {
+            self.idle = None;
+            return;
+        }
+        let since = match self.idle {
+            Some((since, anchor))
+                if (x - f64::from(anchor.0)).hypot(y - f64::from(anchor.1)) <= range * DRIFT_IDLE_TOLERANCE =>
+            {
+                since
+            },
+            _ => {
+                self.idle = Some((now, raw));
+                return;
+            },
+        };
+        if now - since < Duration::from_millis(port.drift_idle_time.into())
+            || (x - self.center.0).hypot(y - self.center.1) > range * DRIFT_MAX_STEP
+        {
+            return;
+        }
+
+        let factor = 1.0 - (-dt.as_secs_f64() / DRIFT_FOLLOW_TIME).exp();
+        let max_offset = range * f64::from(port.drift_max_offset) / 100.0;
+        let follow = |center: f64, target: f64, base: i16| {
+            let base = f64::from(base);
+            (center + (target - center) * factor).clamp(base - max_offset, base + max_offset)
+        };
+        self.center = (follow(self.center.0, x, self.base.0), follow(self.center.1, y, self.base.1));
+        *center = (self.center.0.round() as i16, self.center.1.round() as i16);
+    }
+}
+
+// How long the main stick must be held away from its center before a controller is taken to be digital.
+const RAW_DETECT_TIME: Duration = Duration::from_secs(3);
+// A digital controller only reaches a handful of exact positions, while an analog stick passes through many more.
+const RAW_MAX_POSITIONS: usize = 32;
+// Digital controllers rest exactly at the center and don't have positions this close to it, in raw stick units.
+const RAW_MIN_OFFSET: u8 = 8;
+
+/// Tells digital controllers, which send a few exact stick positions, from analog ones.
+#[derive(Default)]
+pub struct RawDetector {
+    positions: HashSet<(u8, u8)>,
+    /// How long the stick has been away from the center.
+    moved: Duration,
+    last_time: Option<Instant>,
+}
+
+impl RawDetector {
+    /// Record the main stick's position as the adapter reports it. Returns whether the controller is digital,
+    /// once that's clear.
+    pub fn update(&mut self, (x, y): (u8, u8), now: Instant) -> Option<bool> {
+        let dt = self.last_time.map(|t| now - t).unwrap_or_default();
+        self.last_time = Some(now);
+        let offset = (x.abs_diff(0x80), y.abs_diff(0x80));
+        if offset == (0, 0) {
+            return None;
+        }
+        if offset.0 < RAW_MIN_OFFSET && offset.1 < RAW_MIN_OFFSET {
+            // resting slightly off center, or passing through it
+            return Some(false);
+        }
+        self.positions.insert((x, y));
+        self.moved += dt;
+        if self.positions.len() > RAW_MAX_POSITIONS {
+            Some(false)
+        } else if self.moved >= RAW_DETECT_TIME {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(8);
+
+    fn at_angle(degrees: f64, magnitude: f64) -> (i16, i16) {
+        let angle = degrees.to_radians();
+        ((magnitude * angle.cos()).round() as i16, (magnitude * angle.sin()).round() as i16)
+    }
+
+    fn angle_of((x, y): (i16, i16)) -> f64 {
+        f64::from(y).atan2(f64::from(x)).to_degrees().rem_euclid(360.0)
+    }
+
+    #[test]
+    fn snap_angle_snaps_within_window() {
+        let stick = StickConfig { snap_window: 10, ..Default::default() };
+        let snapped = snap_angle(at_angle(50.0, 20000.0), &stick);
+        assert!((angle_of(snapped) - 45.0).abs() < 0.1);
+        assert!((f64::from(snapped.0).hypot(f64::from(snapped.1)) - 20000.0).abs() < 2.0);
+        assert_eq!(snap_angle(at_angle(60.0, 20000.0), &stick), at_angle(60.0, 20000.0));
+    }
+
+    #[test]
+    fn snap_angle_wraps_around() {
+        let stick = StickConfig { snap_window: 10, ..Default::default() };
+        let snapped = snap_angle(at_angle(355.0, 20000.0), &stick);
+        assert_eq!(snapped.1, 0);
+        assert!(snapped.0 > 19990);
+    }
+
+    #[test]
+    fn snap_angle_uses_learned_notches() {
+        let snap_angles: Vec<u16> = (0..360).step_by(45).collect();
+        let notches = snap_angles.iter().map(|&a| f64::from(a) + 8.0).collect();
+        let stick = StickConfig { snap_window: 5, snap_angles, notches, ..Default::default() };
+        assert!((angle_of(snap_angle(at_angle(54.0, 20000.0), &stick)) - 45.0).abs() < 0.1);
+        assert_eq!(snap_angle(at_angle(45.0, 20000.0), &stick), at_angle(45.0, 20000.0));
+    }
+
+    #[test]
+    fn snap_angle_is_off_without_window() {
+        let stick = StickConfig::default();
+        assert_eq!(snap_angle(at_angle(50.0, 20000.0), &stick), at_angle(50.0, 20000.0));
+    }
+
+    #[test]
+    fn notch_learner_finds_notches() {
+        let mut learner = NotchLearner::default();
+        for _ in 0..10 {
+            learner.record(at_angle(50.5, 30000.0));
+            learner.record(at_angle(358.5, 30000.0));
+            // too close to the center to be in a notch
+            learner.record(at_angle(90.5, 5000.0));
+        }
+        let notches = learner.finish(&[45, 0, 90]);
+        assert!((notches[0] - 50.5).abs() < 0.01);
+        assert!((notches[1] - 358.5).abs() < 0.01);
+        assert_eq!(notches[2], 90.0);
+    }
+
+    /// Run drift compensation with the stick resting at `raw` for `time`, returning the center.
+    fn rest_stick(port: &PortConfig, raw: (i16, i16), time: Duration) -> (i16, i16) {
+        let mut state = DriftState::default();
+        let mut center = (0, 0);
+        let start = Instant::now();
+        let mut now = start;
+        while now - start <= time {
+            state.apply(port, raw, &mut center, now);
+            now += FRAME;
+        }
+        center
+    }
+
+    #[test]
+    fn drift_moves_center() {
+        let port = PortConfig { drift_compensation: true, ..Default::default() };
+        let center = rest_stick(&port, (300, -200), Duration::from_secs(10));
+        assert!(center.0 > 250 && center.1 < -150);
+        assert_eq!(rest_stick(&port, (300, -200), Duration::from_secs(1)), (0, 0));
+    }
+
+    #[test]
+    fn drift_ignores_held_tilt() {
+        let port = PortConfig { drift_compensation: true, ..Default::default() };
+        assert_eq!(rest_stick(&port, (2000, 0), Duration::from_secs(10)), (0, 0));
+    }
+
+    #[test]
+    fn drift_is_off_by_default() {
+        assert_eq!(rest_stick(&PortConfig::default(), (300, 0), Duration::from_secs(10)), (0, 0));
+    }
+
+    #[test]
+    fn raw_detector_finds_digital_controllers() {
+        let mut detector = RawDetector::default();
+        let start = Instant::now();
+        let mut now = start;
+        let mut result = None;
+        while now - start <= RAW_DETECT_TIME + FRAME && result.is_none() {
+            // a digital stick jumps straight between a few exact positions
+            let pos = if (now - start).as_millis() % 500 < 250 { (0xd0, 0x80) } else { (0xd0, 0xd0) };
+            result = detector.update(pos, now);
+            now += FRAME;
+        }
+        assert_eq!(result, Some(true));
+    }
+
+    #[test]
+    fn raw_detector_finds_analog_sticks() {
+        let mut detector = RawDetector::default();
+        let now = Instant::now();
+        let result = (0..64).find_map(|i| detector.update((0x90 + i, 0x80), now + FRAME * i.into()));
+        assert_eq!(result, Some(false));
+    }
+
+    #[test]
+    fn raw_detector_waits_at_center() {
+        let mut detector = RawDetector::default();
+        let now = Instant::now();
+        assert_eq!(detector.update((0x80, 0x80), now + Duration::from_secs(10)), None);
+        assert_eq!(detector.update((0x82, 0x7f), now + Duration::from_secs(11)), Some(false));
+    }
+
+    #[test]
+    fn tremor_filter_waits_for_a_steady_direction() {
+        let filter = StickConfig { tremor_time: 50, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        let right = at_angle(0.0, 20000.0);
+        let up = at_angle(90.0, 20000.0);
+        assert_eq!(state.apply(&filter, right, now), (0, 0));
+        assert_eq!(state.apply(&filter, right, now + Duration::from_millis(40)), (0, 0));
+        assert_eq!(state.apply(&filter, right, now + Duration::from_millis(50)), right);
+        // a brief shake in another direction is ignored
+        assert_eq!(state.apply(&filter, up, now + Duration::from_millis(60)), right);
+        assert_eq!(state.apply(&filter, right, now + Duration::from_millis(70)), right);
+        // the stick has to settle again after the shake before it moves
+        let nearby = at_angle(10.0, 25000.0);
+        assert_eq!(state.apply(&filter, nearby, now + Duration::from_millis(110)), right);
+        assert_eq!(state.apply(&filter, nearby, now + Duration::from_millis(120)), nearby);
+    }
+
+    #[test]
+    fn hysteresis_ignores_small_moves() {
+        let filter = StickConfig { hysteresis: 5, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        assert_eq!(state.apply(&filter, (10000, 0), now), (10000, 0));
+        assert_eq!(state.apply(&filter, (10500, 0), now + FRAME), (10000, 0));
+        assert_eq!(state.apply(&filter, (12000, 0), now + FRAME * 2), (12000, 0));
+    }
+
+    #[test]
+    fn hysteresis_returns_to_center() {
+        let filter = StickConfig { hysteresis: 5, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        state.apply(&filter, (1000, -1000), now);
+        assert_eq!(state.apply(&filter, (0, 0), now + FRAME), (0, 0));
+    }
+
+    #[test]
+    fn smoothing_approaches_input() {
+        let filter = StickConfig { smoothing_time: 20, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        state.apply(&filter, (0, 0), now);
+        let first = state.apply(&filter, (20000, 0), now + FRAME);
+        assert!(0 < first.0 && first.0 < 20000);
+        let later = state.apply(&filter, (20000, 0), now + FRAME * 20);
+        assert!(first.0 < later.0 && later.0 <= 20000);
+    }
+
+    #[test]
+    fn snapback_is_held_at_the_center() {
+        let filter = StickConfig { snapback_time: 50, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        state.apply(&filter, (30000, 0), now);
+        // the stick springs back past the center right after being let go
+        assert_eq!(state.apply(&filter, (-8000, 0), now + FRAME), (0, 0));
+        // pushing the other way after the window is real input
+        assert_eq!(state.apply(&filter, (-8000, 0), now + Duration::from_millis(100)), (-8000, 0));
+    }
+
+    #[test]
+    fn snapback_lets_hard_tilts_through() {
+        let filter = StickConfig { snapback_time: 50, ..Default::default() };
+        let mut state = StickFilterState::default();
+        let now = Instant::now();
+        state.apply(&filter, (30000, 0), now);
+        assert_eq!(state.apply(&filter, (-30000, 0), now + FRAME), (-30000, 0));
+    }
+
+    #[test]
+    fn gate_recorder_needs_every_direction() {
+        let mut recorder = GateRecorder::default();
+        for degrees in (0..180).step_by(5) {
+            recorder.record(at_angle(f64::from(degrees), 25000.0));
+        }
+        assert!(recorder.finish().is_none());
+        for degrees in (180..360).step_by(5) {
+            recorder.record(at_angle(f64::from(degrees), 25000.0));
+        }
+        let gate = recorder.finish().expect("the stick went all the way around");
+        assert_eq!(gate.len(), CALIBRATION_SAMPLES);
+        assert!(gate.iter().all(|r| (r - 25000.0 / f64::from(i16::MAX)).abs() < 0.001));
+    }
+
+    #[test]
+    fn gate_recorder_refuses_tiny_gates() {
+        let mut recorder = GateRecorder::default();
+        for degrees in (0..360).step_by(5) {
+            recorder.record(at_angle(f64::from(degrees), 5000.0));
+        }
+        assert!(recorder.finish().is_none());
+    }
+
+    #[test]
+    fn trigger_recorder_needs_enough_travel() {
+        let mut recorder = TriggerRecorder::default();
+        assert!(recorder.finish().is_none());
+        recorder.record(20);
+        recorder.record(80);
+        assert!(recorder.finish().is_none());
+        recorder.record(200);
+        recorder.record(25);
+        assert_eq!(recorder.finish(), Some((20, 200)));
+    }
+}
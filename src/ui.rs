@@ -1,4 +1,8 @@
-use crate::config::{Config, GBUTTONS, xbutton_names};
+use crate::{
+    config::{Config, ConfigProblem, GBUTTONS, mapping_from_name, mapping_index, mapping_name, mapping_names},
+    daemon::PortRequests,
+    dolphin,
+};
 use native_windows_derive::{NwgPartial, NwgUi};
 use native_windows_gui as nwg;
 use native_windows_gui::{
@@ -10,7 +14,7 @@ use native_windows_gui::{
 };
 use nwg::NativeUi;
 use parking_lot::{Mutex, Once};
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use winapi::um::playsoundapi::{PlaySoundA, SND_ALIAS_ID, SND_ASYNC};
 
 const FULL_SIZE: Size<Dimension> = Size { width: Dimension::Percent(1.0), height: Dimension::Percent(1.0) };
@@ -40,81 +44,81 @@ pub struct Port {
     #[nwg_layout_item(layout: layout, col: 0, row: 3)]
     a_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(0))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(1))]
     #[nwg_layout_item(layout: layout, col: 0, row: 4)]
-    a_map: nwg::ComboBox<&'static str>,
+    a_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "B")]
     #[nwg_layout_item(layout: layout, col: 1, row: 3)]
     b_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(2))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(3))]
     #[nwg_layout_item(layout: layout, col: 1, row: 4)]
-    b_map: nwg::ComboBox<&'static str>,
+    b_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "X")]
     #[nwg_layout_item(layout: layout, col: 0, row: 5)]
     x_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(1))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(2))]
     #[nwg_layout_item(layout: layout, col: 0, row: 6)]
-    x_map: nwg::ComboBox<&'static str>,
+    x_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Y")]
     #[nwg_layout_item(layout: layout, col: 1, row: 5)]
     y_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(3))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(4))]
     #[nwg_layout_item(layout: layout, col: 1, row: 6)]
-    y_map: nwg::ComboBox<&'static str>,
+    y_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Z")]
     #[nwg_layout_item(layout: layout, col: 0, row: 7)]
     z_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(5))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(6))]
     #[nwg_layout_item(layout: layout, col: 0, row: 8)]
-    z_map: nwg::ComboBox<&'static str>,
+    z_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Start")]
     #[nwg_layout_item(layout: layout, col: 1, row: 7)]
     st_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(7))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(8))]
     #[nwg_layout_item(layout: layout, col: 1, row: 8)]
-    st_map: nwg::ComboBox<&'static str>,
+    st_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Left")]
     #[nwg_layout_item(layout: layout, col: 0, row: 9)]
     l_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(11))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(12))]
     #[nwg_layout_item(layout: layout, col: 0, row: 10)]
-    l_map: nwg::ComboBox<&'static str>,
+    l_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Right")]
     #[nwg_layout_item(layout: layout, col: 1, row: 9)]
     r_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(12))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(13))]
     #[nwg_layout_item(layout: layout, col: 1, row: 10)]
-    r_map: nwg::ComboBox<&'static str>,
+    r_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Up")]
     #[nwg_layout_item(layout: layout, col: 0, row: 11)]
     u_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(13))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(14))]
     #[nwg_layout_item(layout: layout, col: 0, row: 12)]
-    u_map: nwg::ComboBox<&'static str>,
+    u_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Down")]
     #[nwg_layout_item(layout: layout, col: 1, row: 11)]
     d_label: nwg::Label,
 
-    #[nwg_control(collection: xbutton_names(), selected_index: Some(14))]
+    #[nwg_control(collection: mapping_names(), selected_index: Some(15))]
     #[nwg_layout_item(layout: layout, col: 1, row: 12)]
-    d_map: nwg::ComboBox<&'static str>,
+    d_map: nwg::ComboBox<String>,
 
     #[nwg_control(text: "Recenter:")]
     #[nwg_layout_item(layout: layout, col: 0, row: 13)]
@@ -226,6 +230,20 @@ pub struct App {
     #[nwg_resource]
     tooltip: nwg::Tooltip,
 
+    #[nwg_resource(
+        title: "Import from Dolphin",
+        action: nwg::FileDialogAction::Open,
+        filters: "Dolphin pads and profiles(*.ini)|All files(*.*)"
+    )]
+    dolphin_open_dialog: nwg::FileDialog,
+
+    #[nwg_resource(
+        title: "Export to Dolphin",
+        action: nwg::FileDialogAction::Save,
+        filters: "Dolphin pads and profiles(*.ini)|All files(*.*)"
+    )]
+    dolphin_save_dialog: nwg::FileDialog,
+
     #[nwg_control(popup: true)]
     tray_popup: nwg::Menu,
 
@@ -236,6 +254,149 @@ pub struct App {
     #[nwg_control(parent: tray_popup)]
     sep: nwg::MenuSeparator,
 
+    #[nwg_control(parent: tray_popup, text: "P1")]
+    p1_menu: nwg::Menu,
+
+    #[nwg_control(parent: p1_menu, text: "Enabled")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_port_enabled(SELF, CTRL)])]
+    p1_enabled: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Identify")]
+    #[nwg_events(OnMenuItemSelected: [App::identify(SELF, CTRL)])]
+    p1_identify: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Calibrate")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_calibration(SELF, CTRL)])]
+    p1_calibrate: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Learn notches")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_notch_learning(SELF, CTRL)])]
+    p1_learn: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Use current calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::store_calibration(SELF, CTRL)])]
+    p1_store_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Clear calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::clear_calibration(SELF, CTRL)])]
+    p1_clear_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Import from Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::import_dolphin(SELF, CTRL)])]
+    p1_import: nwg::MenuItem,
+
+    #[nwg_control(parent: p1_menu, text: "Export to Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::export_dolphin(SELF, CTRL)])]
+    p1_export: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_popup, text: "P2")]
+    p2_menu: nwg::Menu,
+
+    #[nwg_control(parent: p2_menu, text: "Enabled")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_port_enabled(SELF, CTRL)])]
+    p2_enabled: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Identify")]
+    #[nwg_events(OnMenuItemSelected: [App::identify(SELF, CTRL)])]
+    p2_identify: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Calibrate")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_calibration(SELF, CTRL)])]
+    p2_calibrate: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Learn notches")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_notch_learning(SELF, CTRL)])]
+    p2_learn: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Use current calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::store_calibration(SELF, CTRL)])]
+    p2_store_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Clear calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::clear_calibration(SELF, CTRL)])]
+    p2_clear_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Import from Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::import_dolphin(SELF, CTRL)])]
+    p2_import: nwg::MenuItem,
+
+    #[nwg_control(parent: p2_menu, text: "Export to Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::export_dolphin(SELF, CTRL)])]
+    p2_export: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_popup, text: "P3")]
+    p3_menu: nwg::Menu,
+
+    #[nwg_control(parent: p3_menu, text: "Enabled")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_port_enabled(SELF, CTRL)])]
+    p3_enabled: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Identify")]
+    #[nwg_events(OnMenuItemSelected: [App::identify(SELF, CTRL)])]
+    p3_identify: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Calibrate")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_calibration(SELF, CTRL)])]
+    p3_calibrate: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Learn notches")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_notch_learning(SELF, CTRL)])]
+    p3_learn: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Use current calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::store_calibration(SELF, CTRL)])]
+    p3_store_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Clear calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::clear_calibration(SELF, CTRL)])]
+    p3_clear_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Import from Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::import_dolphin(SELF, CTRL)])]
+    p3_import: nwg::MenuItem,
+
+    #[nwg_control(parent: p3_menu, text: "Export to Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::export_dolphin(SELF, CTRL)])]
+    p3_export: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_popup, text: "P4")]
+    p4_menu: nwg::Menu,
+
+    #[nwg_control(parent: p4_menu, text: "Enabled")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_port_enabled(SELF, CTRL)])]
+    p4_enabled: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Identify")]
+    #[nwg_events(OnMenuItemSelected: [App::identify(SELF, CTRL)])]
+    p4_identify: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Calibrate")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_calibration(SELF, CTRL)])]
+    p4_calibrate: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Learn notches")]
+    #[nwg_events(OnMenuItemSelected: [App::toggle_notch_learning(SELF, CTRL)])]
+    p4_learn: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Use current calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::store_calibration(SELF, CTRL)])]
+    p4_store_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Clear calibration")]
+    #[nwg_events(OnMenuItemSelected: [App::clear_calibration(SELF, CTRL)])]
+    p4_clear_cal: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Import from Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::import_dolphin(SELF, CTRL)])]
+    p4_import: nwg::MenuItem,
+
+    #[nwg_control(parent: p4_menu, text: "Export to Dolphin...")]
+    #[nwg_events(OnMenuItemSelected: [App::export_dolphin(SELF, CTRL)])]
+    p4_export: nwg::MenuItem,
+
+    #[nwg_control(parent: tray_popup)]
+    port_sep: nwg::MenuSeparator,
+
     #[nwg_control(parent: tray_popup, text: "GitHub")]
     #[nwg_events(OnMenuItemSelected: [App::website])]
     popup_website: nwg::MenuItem,
@@ -258,22 +419,47 @@ pub struct App {
     #[nwg_events(OnNotice: [App::controller_leave])]
     pub leave_notice: nwg::Notice,
 
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::reload_config])]
+    pub reload_notice: nwg::Notice,
+
     #[nwg_control]
     #[nwg_events(OnNotice: [App::exit])]
     pub exit_notice: nwg::Notice,
 
     pub exit_once: Arc<Once>,
 
+    config_path: PathBuf,
+    /// Refuse to use settings with any problems, instead of replacing the bad ones with defaults.
+    strict: bool,
     saved_config: Mutex<Config>,
+    /// The settings file couldn't be read, so it isn't saved over until it's fixed.
+    keep_file: Mutex<bool>,
     config: Arc<Mutex<Config>>,
     deadzone: Mutex<u8>,
 
     must_center: Arc<Mutex<[bool; 4]>>,
     joy_connected: Arc<Mutex<[bool; 4]>>,
+    requests: Arc<Mutex<[PortRequests; 4]>>,
 }
 
 impl App {
     fn right_click(&self) {
+        // the daemon clears these flags when it finishes calibrating
+        let requests = self.requests.lock();
+        for (item, request) in
+            [&self.p1_calibrate, &self.p2_calibrate, &self.p3_calibrate, &self.p4_calibrate].iter().zip(requests.iter())
+        {
+            item.set_checked(request.calibrate);
+        }
+        drop(requests);
+        let config = self.config.lock();
+        for (item, port) in
+            [&self.p1_enabled, &self.p2_enabled, &self.p3_enabled, &self.p4_enabled].iter().zip(&config.ports)
+        {
+            item.set_checked(port.enabled);
+        }
+        drop(config);
         let (x, y) = nwg::GlobalCursor::position();
         self.tray_popup.popup(x, y);
     }
@@ -300,6 +486,7 @@ impl App {
     }
 
     fn show_welcome(&self) {
+        self.log(&format!("Using settings file {}\r\n", self.config_path.display()));
         self.revert_config();
         self.port.revert_button.set_enabled(false);
         self.port.save_button.set_enabled(false);
@@ -323,8 +510,8 @@ impl App {
                 &self.port.d_map,
             ];
             for (but, cb) in config.buttons.iter_mut().zip(button_boxes) {
-                if let Some(sel) = cb.selection() {
-                    *but = sel;
+                if let Some(mapping) = cb.selection_string().and_then(|name| mapping_from_name(&name)) {
+                    *but = mapping;
                 }
             }
             config.deadzone = *self.deadzone.lock();
@@ -367,7 +554,54 @@ impl App {
     }
 
     fn revert_config(&self) {
-        let new_config = Config::load(&|text| self.log(text));
+        let loaded = Config::load(&self.config_path, &|text| self.log(text));
+        self.log_problems(&loaded.problems);
+        *self.keep_file.lock() = loaded.unreadable;
+        if loaded.unreadable {
+            self.log("The settings file won't be saved over until it's fixed.\r\n");
+        }
+        if self.strict && !loaded.problems.is_empty() {
+            self.log("Strict mode is on, so these settings weren't applied.\r\n");
+            return;
+        }
+        self.apply_config(loaded.config);
+    }
+
+    /// Called when the settings file was changed by something else.
+    fn reload_config(&self) {
+        let loaded = Config::reload(&self.config_path);
+        // a reload never replaces working settings with defaults, strict mode or not
+        if !loaded.problems.is_empty() {
+            self.log_problems(&loaded.problems);
+            return;
+        }
+        *self.keep_file.lock() = false;
+        // saving from the UI changes the file too
+        if loaded.config == *self.saved_config.lock() {
+            return;
+        }
+        // don't throw away changes that haven't been saved yet
+        if *self.config.lock() != *self.saved_config.lock() {
+            self.log("Settings file changed, but it wasn't applied over unsaved changes.\r\n");
+            self.log("Use Reload settings to load the file instead.\r\n");
+            return;
+        }
+        self.log("Settings file changed, applying it.\r\n");
+        self.apply_config(loaded.config);
+        self.update_tray_tip();
+    }
+
+    fn log_problems(&self, problems: &[ConfigProblem]) {
+        if !problems.is_empty() {
+            self.log(&format!("Found {} problem(s) with the settings:\r\n", problems.len()));
+        }
+        for problem in problems {
+            self.log(&format!("  {}\r\n", problem));
+        }
+    }
+
+    /// Use settings that were just read from the file, and show them in the UI.
+    fn apply_config(&self, new_config: Config) {
         *self.saved_config.lock() = new_config.clone();
         let mut config = self.config.lock();
         *config = new_config;
@@ -384,7 +618,14 @@ impl App {
             &self.port.u_map,
             &self.port.d_map,
         ]) {
-            cb.set_selection(Some(*but));
+            // a mapping of several buttons gets an entry of its own after the single buttons
+            let mut names = mapping_names();
+            let index = mapping_index(*but).unwrap_or_else(|| {
+                names.push(mapping_name(*but));
+                names.len() - 1
+            });
+            cb.set_collection(names);
+            cb.set_selection(Some(index));
         }
         self.port.recenter_check.set_check_state(if config.auto_recenter {
             CheckBoxState::Checked
@@ -401,8 +642,12 @@ impl App {
     }
 
     fn save_config(&self) {
+        if *self.keep_file.lock() {
+            self.log("Not saving, the settings file couldn't be read. Fix or delete it first.\r\n");
+            return;
+        }
         let config = self.config.lock();
-        if config.save(&|text| self.log(text)) {
+        if config.save(&self.config_path, &|text| self.log(text)) {
             *self.saved_config.lock() = config.clone();
             self.port.revert_button.set_enabled(false);
             self.port.save_button.set_enabled(false);
@@ -488,12 +733,168 @@ impl App {
         self.recenter(3);
     }
 
+    fn toggle_port_enabled(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_enabled, &self.p2_enabled, &self.p3_enabled, &self.p4_enabled];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            let mut config = self.config.lock();
+            let port = &mut config.ports[id];
+            port.enabled = !port.enabled;
+            item.set_checked(port.enabled);
+            let enabled = port.enabled;
+            drop(config);
+            self.log(&format!("P{} {}.\r\n", id + 1, if enabled { "enabled" } else { "disabled" }));
+            self.modify();
+            self.update_tray_tip();
+        }
+    }
+
+    fn identify(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_identify, &self.p2_identify, &self.p3_identify, &self.p4_identify];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            if self.joy_connected.lock()[id] {
+                // pulse as many times as the port number
+                self.requests.lock()[id].identify = id as u8 + 1;
+            } else {
+                self.log(&format!("There's no controller in port {} to identify.\r\n", id + 1));
+            }
+        }
+    }
+
+    fn toggle_calibration(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_calibrate, &self.p2_calibrate, &self.p3_calibrate, &self.p4_calibrate];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            let mut requests = self.requests.lock();
+            let calibrate = &mut requests[id].calibrate;
+            *calibrate = !*calibrate;
+            item.set_checked(*calibrate);
+            if *calibrate {
+                if !self.joy_connected.lock()[id] {
+                    self.log(&format!("P{} will be calibrated once a controller is connected.\r\n", id + 1));
+                }
+                // the daemon writes the calibration to the config
+                self.port.revert_button.set_enabled(true);
+                self.port.save_button.set_enabled(true);
+            }
+        }
+    }
+
+    fn toggle_notch_learning(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_learn, &self.p2_learn, &self.p3_learn, &self.p4_learn];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            let mut requests = self.requests.lock();
+            let learning = &mut requests[id].notch_learning;
+            *learning = !*learning;
+            item.set_checked(*learning);
+            if !*learning {
+                // the daemon writes the learned notches to the config
+                self.port.revert_button.set_enabled(true);
+                self.port.save_button.set_enabled(true);
+            }
+        }
+    }
+
+    fn store_calibration(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_store_cal, &self.p2_store_cal, &self.p3_store_cal, &self.p4_store_cal];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            if self.joy_connected.lock()[id] {
+                self.requests.lock()[id].store_calibration = true;
+                // the daemon writes the calibration to the config
+                self.port.revert_button.set_enabled(true);
+                self.port.save_button.set_enabled(true);
+            } else {
+                self.log(&format!("There's no controller in port {} to calibrate.\r\n", id + 1));
+            }
+        }
+    }
+
+    fn clear_calibration(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_clear_cal, &self.p2_clear_cal, &self.p3_clear_cal, &self.p4_clear_cal];
+        if let Some(id) = items.iter().position(|it| it.handle == item.handle) {
+            self.config.lock().ports[id].calibration = None;
+            self.log(&format!("Calibration cleared for P{}. Save settings to keep this.\r\n", id + 1));
+            self.modify();
+        }
+    }
+
+    fn import_dolphin(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_import, &self.p2_import, &self.p3_import, &self.p4_import];
+        let Some(id) = items.iter().position(|it| it.handle == item.handle) else {
+            return;
+        };
+        if !self.dolphin_open_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(path) = self.dolphin_open_dialog.get_selected_item().map(PathBuf::from) else {
+            return;
+        };
+        match dolphin::import(&path, id) {
+            Ok(pad) => {
+                let deadzone = pad.deadzone;
+                pad.apply(&mut self.config.lock(), id);
+                let current = *self.deadzone.lock();
+                // the dead zone is shared by every port, so only change it if asked to
+                match deadzone {
+                    Some(deadzone)
+                        if deadzone != current
+                            && self.confirm(
+                                "Dolphin dead zone",
+                                &format!(
+                                    "This Dolphin pad has a {}% dead zone, and GC-X's is {}%.\n\
+                                    GC-X's dead zone is used by every port. Change it to {}%?",
+                                    deadzone, current, deadzone
+                                ),
+                            ) =>
+                    {
+                        self.set_deadzone(deadzone, true)
+                    },
+                    _ => self.modify(),
+                }
+                self.log(&format!(
+                    "Imported P{} from {}. It's used once a controller is connected, save settings to keep it.\r\n",
+                    id + 1,
+                    path.display()
+                ));
+            },
+            Err(e) => self.log(&format!("Couldn't import P{} from {}: {}\r\n", id + 1, path.display(), e)),
+        }
+    }
+
+    /// Ask a yes or no question over the window.
+    fn confirm(&self, title: &str, content: &str) -> bool {
+        let params = nwg::MessageParams {
+            title,
+            content,
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Question,
+        };
+        nwg::modal_message(&self.window, &params) == nwg::MessageChoice::Yes
+    }
+
+    fn export_dolphin(&self, item: &nwg::MenuItem) {
+        let items = [&self.p1_export, &self.p2_export, &self.p3_export, &self.p4_export];
+        let Some(id) = items.iter().position(|it| it.handle == item.handle) else {
+            return;
+        };
+        if !self.dolphin_save_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(path) = self.dolphin_save_dialog.get_selected_item().map(PathBuf::from) else {
+            return;
+        };
+        match dolphin::export(&path, id, &self.config.lock()) {
+            Ok(()) => self.log(&format!("Exported P{} to {}.\r\n", id + 1, path.display())),
+            Err(e) => self.log(&format!("Couldn't export P{} to {}: {}\r\n", id + 1, path.display(), e)),
+        }
+    }
+
     fn controller_join(&self) {
         let joy_connected = self.joy_connected.lock();
         self.port.recenter_p1.set_enabled(joy_connected[0]);
         self.port.recenter_p2.set_enabled(joy_connected[1]);
         self.port.recenter_p3.set_enabled(joy_connected[2]);
         self.port.recenter_p4.set_enabled(joy_connected[3]);
+        drop(joy_connected);
+        self.update_tray_tip();
     }
 
     fn controller_leave(&self) {
@@ -502,6 +903,22 @@ impl App {
         self.port.recenter_p2.set_enabled(joy_connected[1]);
         self.port.recenter_p3.set_enabled(joy_connected[2]);
         self.port.recenter_p4.set_enabled(joy_connected[3]);
+        drop(joy_connected);
+        self.update_tray_tip();
+    }
+
+    /// Show which player each connected controller is in the tray icon's tooltip.
+    fn update_tray_tip(&self) {
+        let mut tip = String::from("GC-X");
+        let config = self.config.lock();
+        for (i, (request, port)) in self.requests.lock().iter().zip(&config.ports).enumerate() {
+            if !port.enabled {
+                tip += &format!("\r\nP{}: ignored", i + 1);
+            } else if let Some(player) = request.player {
+                tip += &format!("\r\nP{}: player {}", i + 1, player + 1);
+            }
+        }
+        self.tray.set_tip(&tip);
     }
 
     fn website(&self) {
@@ -523,6 +940,7 @@ pub struct UiInfo {
     pub logger: Logger,
     pub join_sender: nwg::NoticeSender,
     pub leave_sender: nwg::NoticeSender,
+    pub reload_sender: nwg::NoticeSender,
     pub exit_sender: nwg::NoticeSender,
 }
 
@@ -532,9 +950,12 @@ pub fn run_ui() {
 
 pub fn init_app(
     exit_once: Arc<Once>,
+    config_path: PathBuf,
+    strict: bool,
     config: Arc<Mutex<Config>>,
     must_center: Arc<Mutex<[bool; 4]>>,
     joy_connected: Arc<Mutex<[bool; 4]>>,
+    requests: Arc<Mutex<[PortRequests; 4]>>,
 ) -> Result<UiInfo, nwg::NwgError> {
     nwg::init()?;
     let saved_config = Mutex::new(config.lock().clone());
@@ -552,22 +973,66 @@ pub fn init_app(
         port_frame: Default::default(),
         port: Default::default(),
         tooltip: Default::default(),
+        dolphin_open_dialog: Default::default(),
+        dolphin_save_dialog: Default::default(),
         tray_popup: Default::default(),
         popup_title: Default::default(),
         sep: Default::default(),
+        p1_menu: Default::default(),
+        p1_enabled: Default::default(),
+        p1_identify: Default::default(),
+        p1_calibrate: Default::default(),
+        p1_learn: Default::default(),
+        p1_store_cal: Default::default(),
+        p1_clear_cal: Default::default(),
+        p1_import: Default::default(),
+        p1_export: Default::default(),
+        p2_menu: Default::default(),
+        p2_enabled: Default::default(),
+        p2_identify: Default::default(),
+        p2_calibrate: Default::default(),
+        p2_learn: Default::default(),
+        p2_store_cal: Default::default(),
+        p2_clear_cal: Default::default(),
+        p2_import: Default::default(),
+        p2_export: Default::default(),
+        p3_menu: Default::default(),
+        p3_enabled: Default::default(),
+        p3_identify: Default::default(),
+        p3_calibrate: Default::default(),
+        p3_learn: Default::default(),
+        p3_store_cal: Default::default(),
+        p3_clear_cal: Default::default(),
+        p3_import: Default::default(),
+        p3_export: Default::default(),
+        p4_menu: Default::default(),
+        p4_enabled: Default::default(),
+        p4_identify: Default::default(),
+        p4_calibrate: Default::default(),
+        p4_learn: Default::default(),
+        p4_store_cal: Default::default(),
+        p4_clear_cal: Default::default(),
+        p4_import: Default::default(),
+        p4_export: Default::default(),
+        port_sep: Default::default(),
         popup_website: Default::default(),
         exit_item: Default::default(),
         tray: Default::default(),
         has_collapsed: Mutex::new(false),
         join_notice: Default::default(),
         leave_notice: Default::default(),
+        reload_notice: Default::default(),
         exit_notice: Default::default(),
         exit_once,
+        config_path,
+        strict,
         saved_config,
+        keep_file: Mutex::new(false),
         config,
         deadzone: Default::default(),
         must_center,
         joy_connected,
+        requests,
     };
     let app = App::build_ui(app)?;
     app.tooltip.register(&app.port.deadzone_text, "Joystick deadzone, in %");
@@ -593,8 +1058,9 @@ pub fn init_app(
     let logger = Logger { buf: app.log_buf.clone(), sender: app.log_notice.sender() };
     let join_sender = app.join_notice.sender();
     let leave_sender = app.leave_notice.sender();
+    let reload_sender = app.reload_notice.sender();
     let exit_sender = app.exit_notice.sender();
-    Ok(UiInfo { app, logger, join_sender, leave_sender, exit_sender })
+    Ok(UiInfo { app, logger, join_sender, leave_sender, reload_sender, exit_sender })
 }
 
 pub fn show_error(title: &str, content: &str) {
@@ -145,6 +145,7 @@ pub struct Target {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy, Default)]
 pub struct UsbReport {
     pub buttons: u16,
     pub left_trigger: u8,